@@ -0,0 +1,903 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::Duration;
+use serenity::all::{ButtonStyle, ChannelId, ChannelType, CommandInteraction, CommandOptionType, ComponentInteraction, CreateButton, CreateChannel, CreateCommand, CreateCommandOption, EditRole, GuildChannel, GuildId, Member, PermissionOverwrite, PermissionOverwriteType, Permissions, ResolvedValue, Role, RoleId};
+use serenity::builder::{CreateInteractionResponse, CreateInteractionResponseMessage, EditInteractionResponse};
+use serenity::client::Context;
+use serenity::model::colour;
+use crate::chapters::{Chapter, ChapterStatus, Chapters};
+use crate::config::Config;
+use crate::member_db::{MemberJoinMessage, MemberJoinMessageStage};
+use crate::member_info::rebuild_card;
+use crate::message_command;
+use crate::nickname::apply_nickname_policy;
+use crate::permissions::{authorize_command, authorize_component, Action};
+use crate::templates::Templates;
+use crate::waitlist;
+
+// Imports waiting for confirmation, keyed by the user who ran the command.
+static PENDING_IMPORTS: RwLock<Option<HashMap<u64, Chapters>>> = RwLock::new(None);
+
+// Every provisioned chapter role gets the guild's purple so they read as one set.
+const CHAPTER_ROLE_COLOUR: u32 = 0xA724FF;
+const CHAPTER_CATEGORY_NAME: &str = "Chapters";
+// Replies are cut here to stay under Discord's 2000 character limit.
+pub(crate) const MAX_REPLY_LENGTH: usize = 1900;
+// Discord returns at most this many members per request.
+const MEMBER_PAGE_SIZE: u64 = 1000;
+// A merge moves members in batches with a pause in between, so a large chapter does not
+// eat the rate limit the rest of the bot relies on.
+const MERGE_BATCH_SIZE: usize = 10;
+const MERGE_BATCH_PAUSE: Duration = Duration::from_secs(5);
+
+enum ProvisionStep {
+    CreateRole { chapter: usize },
+    UseExistingRole { chapter: usize, role_id: RoleId },
+    CreateCategory,
+    UseExistingCategory { channel_id: ChannelId },
+    CreateChannel { chapter: usize },
+}
+
+pub async fn register_chapter_command() -> CreateCommand {
+    let dry_run = CreateCommandOption::new(CommandOptionType::Boolean, "dry_run", "Only list what would be created.")
+        .required(false);
+    let channels = CreateCommandOption::new(CommandOptionType::Boolean, "channels", "Also create a private channel per chapter under a Chapters category.")
+        .required(false);
+
+    let provision = CreateCommandOption::new(CommandOptionType::SubCommand, "provision", "Create missing chapter roles and write their ids to chapters.json.")
+        .add_sub_option(dry_run)
+        .add_sub_option(channels);
+
+    let pattern = CreateCommandOption::new(CommandOptionType::String, "pattern", "Role name pattern such as \"Chapter - {realm}\". Without it roles must be named after a chapter.")
+        .required(false);
+
+    let import = CreateCommandOption::new(CommandOptionType::SubCommand, "import", "Propose chapters.json from the guild's existing roles.")
+        .add_sub_option(pattern);
+
+    let archive = CreateCommandOption::new(CommandOptionType::SubCommand, "archive", "Stop offering a chapter to new members. Its members and records are kept.")
+        .add_sub_option(CreateCommandOption::new(CommandOptionType::String, "chapter", "Name of the chapter to archive.").required(true));
+
+    let rename = CreateCommandOption::new(CommandOptionType::SubCommand, "rename", "Rename a chapter and the member records that name it.")
+        .add_sub_option(CreateCommandOption::new(CommandOptionType::String, "chapter", "Current name of the chapter.").required(true))
+        .add_sub_option(CreateCommandOption::new(CommandOptionType::String, "name", "New name.").required(true));
+
+    let merge = CreateCommandOption::new(CommandOptionType::SubCommand, "merge", "Move every member of one chapter to another, then archive the first.")
+        .add_sub_option(CreateCommandOption::new(CommandOptionType::String, "from", "Chapter being folded in.").required(true))
+        .add_sub_option(CreateCommandOption::new(CommandOptionType::String, "into", "Chapter its members move to.").required(true));
+
+    let mut status_choice = CreateCommandOption::new(CommandOptionType::String, "status", "Open takes members, waitlist queues them, closed also hides the chapter.")
+        .required(true);
+    for status in ChapterStatus::ALL {
+        status_choice = status_choice.add_string_choice(status.key(), status.key());
+    }
+    let status = CreateCommandOption::new(CommandOptionType::SubCommand, "status", "Set whether a chapter takes new members and how many.")
+        .add_sub_option(CreateCommandOption::new(CommandOptionType::String, "chapter", "Name of the chapter.").required(true))
+        .add_sub_option(status_choice)
+        .add_sub_option(CreateCommandOption::new(CommandOptionType::Integer, "cap", "Most members the chapter takes. 0 removes the cap.").min_int_value(0).required(false));
+
+    CreateCommand::new("chapter").description("Manage chapters.")
+        .add_option(provision)
+        .add_option(import)
+        .add_option(archive)
+        .add_option(rename)
+        .add_option(merge)
+        .add_option(status)
+}
+
+pub async fn handle_chapter_command(ctx: &Context, command: &CommandInteraction) -> Result<(), serenity::Error> {
+    let subcommand = command.data.options().first().map(|option| option.name).unwrap_or_default();
+
+    match subcommand {
+        "provision" => handle_provision(ctx, command).await,
+        "import" => handle_import(ctx, command).await,
+        "archive" => handle_archive(ctx, command).await,
+        "rename" => handle_rename(ctx, command).await,
+        "merge" => handle_merge(ctx, command).await,
+        "status" => handle_status(ctx, command).await,
+        _ => Ok(()),
+    }
+}
+
+async fn handle_provision(ctx: &Context, command: &CommandInteraction) -> Result<(), serenity::Error> {
+    if !authorize_command(ctx, command, Action::Admin).await? {
+        return Ok(());
+    }
+
+    let mut dry_run = false;
+    let mut with_channels = false;
+    for option in command.data.options() {
+        if let ResolvedValue::SubCommand(sub_options) = option.value {
+            for sub_option in sub_options {
+                match (sub_option.name, sub_option.value) {
+                    ("dry_run", ResolvedValue::Boolean(value)) => dry_run = value,
+                    ("channels", ResolvedValue::Boolean(value)) => with_channels = value,
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    // Creating dozens of roles and channels takes longer than the three seconds Discord waits for a reply.
+    command.create_response(
+        &ctx.http,
+        CreateInteractionResponse::Defer(CreateInteractionResponseMessage::new().ephemeral(true)),
+    ).await?;
+
+    let templates = Templates::for_locale(&command.locale);
+    let guild_id = command.guild_id.ok_or(serenity::Error::Other("No guild ID found"))?;
+    let roles = guild_id.roles(&ctx.http).await?;
+    let channels = guild_id.channels(&ctx.http).await?;
+    let mut chapters = Chapters::for_guild(guild_id);
+
+    let steps = plan_provisioning(&chapters, &roles, &channels, with_channels);
+    if steps.is_empty() {
+        command.edit_response(&ctx.http, EditInteractionResponse::new().content(templates.text("provision_nothing", &[]))).await?;
+        return Ok(());
+    }
+
+    let changes = steps.iter()
+        .map(|step| describe_step(&templates, &chapters, step))
+        .collect::<Vec<String>>();
+
+    if dry_run {
+        let reply = templates.text("provision_dry_run", &[("changes", join_lines(&changes, MAX_REPLY_LENGTH))]);
+        command.edit_response(&ctx.http, EditInteractionResponse::new().content(reply)).await?;
+        return Ok(());
+    }
+
+    let result = apply_provisioning(ctx, guild_id, &mut chapters, &steps).await;
+
+    // Save whatever was created, even if a later step failed, so a re-run picks up where this one stopped.
+    let saved = chapters.save();
+
+    let mut reply = match result {
+        Ok(()) => templates.text("provision_done", &[("changes", join_lines(&changes, MAX_REPLY_LENGTH))]),
+        Err(e) => {
+            println!("Error provisioning chapters: {}", e);
+            templates.text("provision_failed", &[("error", e.to_string())])
+        }
+    };
+    if let Err(e) = saved {
+        reply = not_saved(&templates, e);
+    }
+    command.edit_response(&ctx.http, EditInteractionResponse::new().content(reply)).await?;
+
+    Ok(())
+}
+
+/// Works out what is missing. A chapter whose role id is unset or deleted reuses a role with
+/// its name when one exists, and only otherwise gets a new role.
+fn plan_provisioning(chapters: &Chapters, roles: &HashMap<RoleId, Role>, channels: &HashMap<ChannelId, GuildChannel>, with_channels: bool) -> Vec<ProvisionStep> {
+    let mut steps = Vec::new();
+
+    // Archived chapters keep whatever they had and get nothing new.
+    for (index, chapter) in chapters.chapters.iter().enumerate().filter(|(_, chapter)| !chapter.archived) {
+        if chapter.role_id != 0 && roles.contains_key(&RoleId::new(chapter.role_id)) {
+            continue;
+        }
+
+        match roles.values().find(|role| role.name == chapter.name) {
+            Some(role) => steps.push(ProvisionStep::UseExistingRole { chapter: index, role_id: role.id }),
+            None => steps.push(ProvisionStep::CreateRole { chapter: index }),
+        }
+    }
+
+    if !with_channels {
+        return steps;
+    }
+
+    let missing_channels = chapters.chapters.iter()
+        .enumerate()
+        .filter(|(_, chapter)| !chapter.archived)
+        .filter(|(_, chapter)| !chapter.announcement_channel_id.is_some_and(|channel_id| channels.contains_key(&ChannelId::new(channel_id))))
+        .map(|(index, _)| ProvisionStep::CreateChannel { chapter: index })
+        .collect::<Vec<ProvisionStep>>();
+    if missing_channels.is_empty() {
+        return steps;
+    }
+
+    let category = channels.values().find(|channel| channel.kind == ChannelType::Category && channel.name == CHAPTER_CATEGORY_NAME);
+    match category {
+        Some(category) => steps.push(ProvisionStep::UseExistingCategory { channel_id: category.id }),
+        None => steps.push(ProvisionStep::CreateCategory),
+    }
+    steps.extend(missing_channels);
+
+    steps
+}
+
+/// Runs the steps in order. Roles come first so the channel overwrites can refer to them.
+async fn apply_provisioning(ctx: &Context, guild_id: GuildId, chapters: &mut Chapters, steps: &[ProvisionStep]) -> Result<(), serenity::Error> {
+    let mut category_id = None;
+
+    for step in steps {
+        match step {
+            ProvisionStep::CreateRole { chapter } => {
+                let name = chapters.chapters[*chapter].name.clone();
+                let role = guild_id.create_role(&ctx.http, EditRole::new()
+                    .name(name)
+                    .colour(colour::Color::new(CHAPTER_ROLE_COLOUR))
+                    .mentionable(true)
+                ).await?;
+                chapters.chapters[*chapter].role_id = role.id.get();
+            }
+            ProvisionStep::UseExistingRole { chapter, role_id } => {
+                chapters.chapters[*chapter].role_id = role_id.get();
+            }
+            ProvisionStep::CreateCategory => {
+                let category = guild_id.create_channel(&ctx.http, CreateChannel::new(CHAPTER_CATEGORY_NAME)
+                    .kind(ChannelType::Category)
+                ).await?;
+                category_id = Some(category.id);
+            }
+            ProvisionStep::UseExistingCategory { channel_id } => {
+                category_id = Some(*channel_id);
+            }
+            ProvisionStep::CreateChannel { chapter } => {
+                let chapter = &mut chapters.chapters[*chapter];
+                let mut builder = CreateChannel::new(chapter.name.to_lowercase())
+                    .kind(ChannelType::Text)
+                    .permissions(chapter_channel_overwrites(ctx, guild_id, chapter.role_id, chapter.officer_role_id));
+                if let Some(category_id) = category_id {
+                    builder = builder.category(category_id);
+                }
+                let channel = guild_id.create_channel(&ctx.http, builder).await?;
+                chapter.announcement_channel_id = Some(channel.id.get());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Hidden from everyone except the chapter, its officers and the bot.
+fn chapter_channel_overwrites(ctx: &Context, guild_id: GuildId, role_id: u64, officer_role_id: Option<u64>) -> Vec<PermissionOverwrite> {
+    let member_permissions = Permissions::VIEW_CHANNEL | Permissions::SEND_MESSAGES | Permissions::READ_MESSAGE_HISTORY;
+
+    let mut overwrites = vec![
+        PermissionOverwrite {
+            allow: Permissions::empty(),
+            deny: Permissions::VIEW_CHANNEL,
+            // The @everyone role shares the guild's id.
+            kind: PermissionOverwriteType::Role(RoleId::new(guild_id.get())),
+        },
+        PermissionOverwrite {
+            allow: member_permissions,
+            deny: Permissions::empty(),
+            kind: PermissionOverwriteType::Role(RoleId::new(role_id)),
+        },
+        PermissionOverwrite {
+            allow: member_permissions | Permissions::EMBED_LINKS,
+            deny: Permissions::empty(),
+            kind: PermissionOverwriteType::Member(ctx.cache.current_user().id),
+        },
+    ];
+    if let Some(officer_role_id) = officer_role_id {
+        overwrites.push(PermissionOverwrite {
+            allow: member_permissions | Permissions::MANAGE_MESSAGES,
+            deny: Permissions::empty(),
+            kind: PermissionOverwriteType::Role(RoleId::new(officer_role_id)),
+        });
+    }
+
+    overwrites
+}
+
+fn describe_step(templates: &Templates, chapters: &Chapters, step: &ProvisionStep) -> String {
+    let chapter_name = |index: &usize| chapters.chapters[*index].name.clone();
+
+    match step {
+        ProvisionStep::CreateRole { chapter } => templates.text("provision_create_role", &[("chapter", chapter_name(chapter))]),
+        ProvisionStep::UseExistingRole { chapter, role_id } => templates.text("provision_existing_role", &[("chapter", chapter_name(chapter)), ("role", format!("<@&{}>", role_id))]),
+        ProvisionStep::CreateCategory => templates.text("provision_create_category", &[("category", CHAPTER_CATEGORY_NAME.to_string())]),
+        ProvisionStep::UseExistingCategory { channel_id } => templates.text("provision_existing_category", &[("category", format!("<#{}>", channel_id))]),
+        ProvisionStep::CreateChannel { chapter } => templates.text("provision_create_channel", &[("chapter", chapter_name(chapter)), ("channel", chapter_name(chapter).to_lowercase())]),
+    }
+}
+
+async fn handle_import(ctx: &Context, command: &CommandInteraction) -> Result<(), serenity::Error> {
+    if !authorize_command(ctx, command, Action::Admin).await? {
+        return Ok(());
+    }
+
+    let mut pattern = None;
+    for option in command.data.options() {
+        if let ResolvedValue::SubCommand(sub_options) = option.value {
+            for sub_option in sub_options {
+                if let ("pattern", ResolvedValue::String(value)) = (sub_option.name, sub_option.value) {
+                    pattern = Some(value.to_string());
+                }
+            }
+        }
+    }
+
+    let templates = Templates::for_locale(&command.locale);
+    let guild_id = command.guild_id.ok_or(serenity::Error::Other("No guild ID found"))?;
+    let roles = guild_id.roles(&ctx.http).await?;
+    let chapters = Chapters::for_guild(guild_id);
+
+    let (proposal, changes) = propose_import(guild_id, &chapters, &roles, pattern.as_deref());
+    let stale = chapters.chapters.iter()
+        .filter(|chapter| chapter.role_id != 0 && !roles.contains_key(&RoleId::new(chapter.role_id)))
+        .map(|chapter| templates.text("import_stale_role", &[("chapter", chapter.name.clone()), ("role_id", chapter.role_id.to_string())]))
+        .collect::<Vec<String>>();
+
+    let mut reply = String::new();
+    if !stale.is_empty() {
+        reply += &templates.text("import_stale", &[("chapters", join_lines(&stale, MAX_REPLY_LENGTH / 3))]);
+        reply += "\n";
+    }
+
+    if changes.is_empty() {
+        reply += &templates.text("import_nothing", &[]);
+        command.create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::default()
+                    .ephemeral(true)
+                    .content(reply)
+            ),
+        ).await?;
+        return Ok(());
+    }
+
+    reply += &templates.text("import_proposal", &[
+        ("count", proposal.get_count().to_string()),
+        ("changes", join_lines(&changes, MAX_REPLY_LENGTH - reply.len())),
+    ]);
+
+    if let Ok(mut pending) = PENDING_IMPORTS.write() {
+        pending.get_or_insert_with(HashMap::new).insert(command.user.id.get(), proposal);
+    }
+
+    let confirm = CreateButton::new("chapter_import_confirm")
+        .label(templates.text("import_confirm", &[]))
+        .style(ButtonStyle::Success);
+    let cancel = CreateButton::new("chapter_import_cancel")
+        .label(templates.text("import_cancel", &[]))
+        .style(ButtonStyle::Secondary);
+
+    command.create_response(
+        &ctx.http,
+        CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::default()
+                .ephemeral(true)
+                .content(reply)
+                .button(confirm)
+                .button(cancel)
+        ),
+    ).await?;
+
+    Ok(())
+}
+
+/// Builds the proposed chapter set from the guild's roles. Existing chapters keep their settings
+/// and only have their role id updated; roles matching the pattern with no chapter yet become new chapters.
+fn propose_import(guild_id: GuildId, chapters: &Chapters, roles: &HashMap<RoleId, Role>, pattern: Option<&str>) -> (Chapters, Vec<String>) {
+    let templates = Templates::for_guild(guild_id);
+    let mut proposal = chapters.clone();
+    let mut changes = Vec::new();
+
+    let mut candidates = roles.values()
+        // @everyone shares the guild's id, and managed roles belong to integrations.
+        .filter(|role| role.id.get() != guild_id.get() && !role.managed)
+        .filter_map(|role| match pattern {
+            Some(pattern) => realm_from_pattern(pattern, &role.name).map(|realm| (realm, role)),
+            None => Some((role.name.clone(), role)),
+        })
+        .collect::<Vec<(String, &Role)>>();
+    candidates.sort_by(|a, b| a.0.cmp(&b.0));
+
+    for (realm, role) in candidates {
+        let role_mention = format!("<@&{}>", role.id);
+        match proposal.chapters.iter_mut().find(|chapter| normalize(&chapter.name) == normalize(&realm)) {
+            Some(chapter) if chapter.role_id == role.id.get() => {}
+            Some(chapter) => {
+                chapter.role_id = role.id.get();
+                changes.push(templates.text("import_matched", &[("chapter", chapter.name.clone()), ("role", role_mention)]));
+            }
+            // Without a pattern every role is a candidate, so only existing chapters are matched.
+            None if pattern.is_none() => {}
+            // New chapters go at the end, so the ids of existing chapters do not change.
+            None => {
+                changes.push(templates.text("import_new", &[("chapter", realm.clone()), ("role", role_mention)]));
+                proposal.chapters.push(Chapter {
+                    name: realm,
+                    role_id: role.id.get(),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+
+    (proposal, changes)
+}
+
+/// Pulls the realm out of a role name such as "Chapter - Area 52" for the pattern "Chapter - {realm}".
+fn realm_from_pattern(pattern: &str, role_name: &str) -> Option<String> {
+    let (prefix, suffix) = pattern.split_once("{realm}")?;
+    let realm = role_name.strip_prefix(prefix)?.strip_suffix(suffix)?.trim();
+    if realm.is_empty() {
+        return None;
+    }
+
+    Some(realm.to_string())
+}
+
+/// "Area 52", "area52" and "Kel'Thuzad"/"KelThuzad" should all find the same chapter.
+fn normalize(name: &str) -> String {
+    name.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect()
+}
+
+pub async fn handle_import_button(ctx: &Context, component: &ComponentInteraction) -> Result<(), serenity::Error> {
+    if !authorize_component(ctx, component, Action::Admin, None).await? {
+        return Ok(());
+    }
+
+    let templates = Templates::for_locale(&component.locale);
+    let proposal = PENDING_IMPORTS.write().ok()
+        .and_then(|mut pending| pending.as_mut()?.remove(&component.user.id.get()));
+
+    let content = match (component.data.custom_id.as_str(), proposal) {
+        ("chapter_import_confirm", Some(mut proposal)) => match proposal.save() {
+            Ok(()) => {
+                if let Err(e) = message_command::refresh_welcome_messages(ctx).await {
+                    println!("Error refreshing welcome messages: {}", e);
+                }
+                templates.text("import_saved", &[("count", proposal.get_count().to_string())])
+            }
+            Err(e) => not_saved(&templates, e),
+        },
+        ("chapter_import_confirm", None) => templates.text("import_expired", &[]),
+        _ => templates.text("import_cancelled", &[]),
+    };
+
+    component.create_response(
+        &ctx.http,
+        CreateInteractionResponse::UpdateMessage(
+            CreateInteractionResponseMessage::new()
+                .content(content)
+                .components(vec![])
+        ),
+    ).await?;
+
+    Ok(())
+}
+
+/// The subcommand's string options by name.
+pub(crate) fn string_options(command: &CommandInteraction) -> HashMap<&str, String> {
+    let mut values = HashMap::new();
+    for option in command.data.options() {
+        if let ResolvedValue::SubCommand(sub_options) = option.value {
+            for sub_option in sub_options {
+                if let ResolvedValue::String(value) = sub_option.value {
+                    values.insert(sub_option.name, value.trim().to_string());
+                }
+            }
+        }
+    }
+    values
+}
+
+async fn handle_archive(ctx: &Context, command: &CommandInteraction) -> Result<(), serenity::Error> {
+    if !authorize_command(ctx, command, Action::Admin).await? {
+        return Ok(());
+    }
+
+    let templates = Templates::for_locale(&command.locale);
+    let guild_id = command.guild_id.ok_or(serenity::Error::Other("No guild ID found"))?;
+    let name = string_options(command).remove("chapter").unwrap_or_default();
+    let mut chapters = Chapters::for_guild(guild_id);
+
+    let reply = match chapters.find_id_by_name(&name).filter(|id| chapters.get_active_by_id(*id).is_some()) {
+        Some(id) => {
+            let chapter_name = chapters.chapters[id].name.clone();
+            match chapters.archive_chapter(id, None) {
+                Ok(()) => {
+                    println!("{} archived chapter {}", command.user.id, chapter_name);
+
+                    if let Err(e) = message_command::refresh_welcome_messages(ctx).await {
+                        println!("Error refreshing welcome messages: {}", e);
+                    }
+                    templates.text("chapter_archived", &[("chapter", chapter_name)])
+                }
+                Err(e) => not_saved(&templates, e),
+            }
+        }
+        None => templates.text("chapter_unknown", &[("chapter", name)]),
+    };
+
+    command.create_response(
+        &ctx.http,
+        CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::default()
+                .ephemeral(true)
+                .content(reply)
+        ),
+    ).await?;
+
+    Ok(())
+}
+
+async fn handle_rename(ctx: &Context, command: &CommandInteraction) -> Result<(), serenity::Error> {
+    if !authorize_command(ctx, command, Action::Admin).await? {
+        return Ok(());
+    }
+
+    let templates = Templates::for_locale(&command.locale);
+    let guild_id = command.guild_id.ok_or(serenity::Error::Other("No guild ID found"))?;
+    let mut options = string_options(command);
+    let name = options.remove("chapter").unwrap_or_default();
+    let new_name = options.remove("name").unwrap_or_default();
+    let mut chapters = Chapters::for_guild(guild_id);
+
+    let reply = match (chapters.find_id_by_name(&name), chapters.find_id_by_name(&new_name)) {
+        (None, _) => templates.text("chapter_unknown", &[("chapter", name)]),
+        // Renaming to a different case of the same name is allowed.
+        (Some(id), Some(existing_id)) if existing_id != id => templates.text("chapter_name_taken", &[("chapter", new_name)]),
+        (Some(_), _) if new_name.is_empty() => templates.text("chapter_unknown", &[("chapter", new_name)]),
+        (Some(id), _) => {
+            let chapter = chapters.chapters[id].clone();
+            // The role goes first, so a failure leaves the chapter file and the records untouched.
+            match rename_chapter_role(ctx, guild_id, &chapter, &new_name).await {
+                Err(e) => {
+                    println!("Error renaming role of chapter {}: {}", chapter.name, e);
+                    templates.text("chapter_rename_failed", &[("chapter", chapter.name), ("error", e.to_string())])
+                }
+                Ok(()) => {
+                    // Records keep the old name too, so they still match the chapter file.
+                    if let Err(e) = chapters.rename_chapter(id, new_name.clone()) {
+                        return reply_not_saved(ctx, command, &templates, e).await;
+                    }
+                    println!("{} renamed chapter {} to {}", command.user.id, chapter.name, new_name);
+
+                    let records = match MemberJoinMessage::rename_chapter(guild_id.to_string(), chapter.name.clone(), new_name.clone()).await {
+                        Ok(records) => records,
+                        Err(e) => {
+                            println!("Error renaming chapter in database: {}", e);
+                            0
+                        }
+                    };
+
+                    if let Err(e) = message_command::refresh_welcome_messages(ctx).await {
+                        println!("Error refreshing welcome messages: {}", e);
+                    }
+                    templates.text("chapter_renamed", &[("old", chapter.name), ("new", new_name), ("records", records.to_string())])
+                }
+            }
+        }
+    };
+
+    command.create_response(
+        &ctx.http,
+        CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::default()
+                .ephemeral(true)
+                .content(reply)
+        ),
+    ).await?;
+
+    Ok(())
+}
+
+/// A role named after the chapter, as /chapter provision creates them, follows the rename.
+async fn rename_chapter_role(ctx: &Context, guild_id: GuildId, chapter: &Chapter, new_name: &str) -> Result<(), serenity::Error> {
+    if chapter.role_id == 0 {
+        return Ok(());
+    }
+
+    let roles = guild_id.roles(&ctx.http).await?;
+    if roles.get(&RoleId::new(chapter.role_id)).is_some_and(|role| role.name == chapter.name) {
+        guild_id.edit_role(&ctx.http, RoleId::new(chapter.role_id), EditRole::new().name(new_name)).await?;
+    }
+
+    Ok(())
+}
+
+async fn handle_merge(ctx: &Context, command: &CommandInteraction) -> Result<(), serenity::Error> {
+    if !authorize_command(ctx, command, Action::Admin).await? {
+        return Ok(());
+    }
+
+    let templates = Templates::for_locale(&command.locale);
+    let guild_id = command.guild_id.ok_or(serenity::Error::Other("No guild ID found"))?;
+    let mut options = string_options(command);
+    let from_name = options.remove("from").unwrap_or_default();
+    let into_name = options.remove("into").unwrap_or_default();
+    let mut chapters = Chapters::for_guild(guild_id);
+
+    let active_id = |name: &str| chapters.find_id_by_name(name).filter(|id| chapters.get_active_by_id(*id).is_some());
+    let problem = match (active_id(&from_name), active_id(&into_name)) {
+        (None, _) => Some(templates.text("chapter_unknown", &[("chapter", from_name.clone())])),
+        (_, None) => Some(templates.text("chapter_unknown", &[("chapter", into_name.clone())])),
+        (Some(from_id), Some(into_id)) if from_id == into_id => Some(templates.text("chapter_merge_same", &[])),
+        _ => None,
+    };
+    if let Some(problem) = problem {
+        command.create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::default()
+                    .ephemeral(true)
+                    .content(problem)
+            ),
+        ).await?;
+        return Ok(());
+    }
+    let from_id = active_id(&from_name).unwrap_or_default();
+    let from = chapters.chapters[from_id].clone();
+    let into = chapters.chapters[active_id(&into_name).unwrap_or_default()].clone();
+
+    // Moving a big chapter takes minutes, far longer than the three seconds Discord waits for a reply.
+    command.create_response(
+        &ctx.http,
+        CreateInteractionResponse::Defer(CreateInteractionResponseMessage::new().ephemeral(true)),
+    ).await?;
+
+    println!("{} is merging chapter {} into {}", command.user.id, from.name, into.name);
+    // Once deferred, every outcome has to replace the "thinking…" response.
+    let reply = match merge_chapters(ctx, guild_id, &mut chapters, from_id, &from, &into, &templates).await {
+        Ok(reply) => reply,
+        Err(e) => {
+            println!("Error merging chapter {} into {}: {}", from.name, into.name, e);
+            templates.text("chapter_merge_error", &[("from", from.name.clone()), ("into", into.name.clone()), ("error", e.to_string())])
+        }
+    };
+    command.edit_response(&ctx.http, EditInteractionResponse::new().content(reply)).await?;
+
+    Ok(())
+}
+
+/// Moves the members of `from` into `into`, then archives `from`. Returns the reply for the admin.
+async fn merge_chapters(ctx: &Context, guild_id: GuildId, chapters: &mut Chapters, from_id: usize, from: &Chapter, into: &Chapter, templates: &Templates) -> Result<String, serenity::Error> {
+    let config = Config::for_guild(guild_id);
+    let holders = role_holders(ctx, guild_id, from.role_id).await?;
+
+    let mut moved = 0;
+    let mut failed = Vec::new();
+    for (index, member) in holders.iter().enumerate() {
+        if index > 0 && index % MERGE_BATCH_SIZE == 0 {
+            tokio::time::sleep(MERGE_BATCH_PAUSE).await;
+        }
+
+        match move_member(ctx, &config, member, from, into).await {
+            Ok(()) => moved += 1,
+            Err(e) => {
+                println!("Error moving {} from {} to {}: {}", member.user.id, from.name, into.name, e);
+                failed.push(format!("<@{}>", member.user.id));
+            }
+        }
+    }
+
+    // Open cards show the chapter, so they are drawn again once their records have moved.
+    let open_cards = match MemberJoinMessage::get_all(guild_id.to_string()).await {
+        Ok(records) => MemberJoinMessage::latest_per_member(records).into_iter()
+            .filter(|record| record.chapter_name == from.name)
+            .filter(|record| matches!(record.stage, MemberJoinMessageStage::NewMember | MemberJoinMessageStage::Onboarding))
+            .map(|record| record.message_id)
+            .collect::<Vec<u64>>(),
+        Err(e) => {
+            println!("Error getting records of chapter {} from database: {}", from.name, e);
+            Vec::new()
+        }
+    };
+
+    let records = match MemberJoinMessage::merge_chapter(guild_id.to_string(), from.name.clone(), into.name.clone(), into.flavour).await {
+        Ok(records) => records,
+        Err(e) => {
+            println!("Error moving chapter records in database: {}", e);
+            0
+        }
+    };
+
+    for (index, message_id) in open_cards.into_iter().enumerate() {
+        if index > 0 && index % MERGE_BATCH_SIZE == 0 {
+            tokio::time::sleep(MERGE_BATCH_PAUSE).await;
+        }
+        let Ok(record) = MemberJoinMessage::get_message_by_message_id(message_id.to_string()).await else {
+            continue;
+        };
+        if let Err(e) = rebuild_card(ctx, guild_id, &record).await {
+            println!("Error redrawing card of {}: {}", record.discord_user_id, e);
+        }
+    }
+
+    let saved = chapters.archive_chapter(from_id, Some(into.name.clone()));
+    if let Err(e) = message_command::refresh_welcome_messages(ctx).await {
+        println!("Error refreshing welcome messages: {}", e);
+    }
+
+    let mut reply = templates.text("chapter_merged", &[
+        ("from", from.name.clone()),
+        ("into", into.name.clone()),
+        ("moved", moved.to_string()),
+        ("records", records.to_string()),
+    ]);
+    if !failed.is_empty() {
+        reply += "\n";
+        reply += &templates.text("chapter_merge_failed", &[("members", join_lines(&failed, MAX_REPLY_LENGTH - reply.len()))]);
+    }
+    // The members have moved either way, so the admin hears both.
+    if let Err(e) = saved {
+        reply += "\n";
+        reply += &not_saved(templates, e);
+    }
+
+    Ok(reply)
+}
+
+async fn handle_status(ctx: &Context, command: &CommandInteraction) -> Result<(), serenity::Error> {
+    if !authorize_command(ctx, command, Action::Admin).await? {
+        return Ok(());
+    }
+
+    let templates = Templates::for_locale(&command.locale);
+    let guild_id = command.guild_id.ok_or(serenity::Error::Other("No guild ID found"))?;
+    let mut options = string_options(command);
+    let name = options.remove("chapter").unwrap_or_default();
+    let status = options.remove("status").and_then(|key| ChapterStatus::from_key(&key)).unwrap_or_default();
+    let mut cap = None;
+    for option in command.data.options() {
+        if let ResolvedValue::SubCommand(sub_options) = option.value {
+            for sub_option in sub_options {
+                if let ("cap", ResolvedValue::Integer(value)) = (sub_option.name, sub_option.value) {
+                    cap = Some(value);
+                }
+            }
+        }
+    }
+    let mut chapters = Chapters::for_guild(guild_id);
+
+    let reply = match chapters.find_id_by_name(&name).filter(|id| chapters.get_active_by_id(*id).is_some()) {
+        Some(id) => {
+            // Leaving the cap out keeps the current one.
+            let member_cap = match cap {
+                Some(0) => None,
+                Some(cap) => Some(cap as u32),
+                None => chapters.chapters[id].member_cap,
+            };
+            if let Err(e) = chapters.set_recruitment(id, status, member_cap) {
+                return reply_not_saved(ctx, command, &templates, e).await;
+            }
+            let chapter = chapters.chapters[id].clone();
+            println!("{} set chapter {} to {} with cap {:?}", command.user.id, chapter.name, status.key(), member_cap);
+
+            if let Err(e) = message_command::refresh_welcome_messages(ctx).await {
+                println!("Error refreshing welcome messages: {}", e);
+            }
+            waitlist::notify_open_slots(ctx, guild_id, &chapter.name).await;
+
+            let members = MemberJoinMessage::count_in_chapter(guild_id.to_string(), chapter.name.clone()).await.unwrap_or_default();
+            let vars = [
+                ("chapter", chapter.name.clone()),
+                ("status", status.key().to_string()),
+                ("members", members.to_string()),
+                ("cap", member_cap.map(|cap| cap.to_string()).unwrap_or_default()),
+            ];
+            match member_cap {
+                Some(_) => templates.text("chapter_status_set_capped", &vars),
+                None => templates.text("chapter_status_set", &vars),
+            }
+        }
+        None => templates.text("chapter_unknown", &[("chapter", name)]),
+    };
+
+    command.create_response(
+        &ctx.http,
+        CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::default()
+                .ephemeral(true)
+                .content(reply)
+        ),
+    ).await?;
+
+    Ok(())
+}
+
+/// Every member holding the role, fetched a page at a time.
+async fn role_holders(ctx: &Context, guild_id: GuildId, role_id: u64) -> Result<Vec<Member>, serenity::Error> {
+    let mut holders = Vec::new();
+    if role_id == 0 {
+        return Ok(holders);
+    }
+
+    let mut after = None;
+    loop {
+        let page = guild_id.members(&ctx.http, Some(MEMBER_PAGE_SIZE), after).await?;
+        let last_page = (page.len() as u64) < MEMBER_PAGE_SIZE;
+        after = page.last().map(|member| member.user.id);
+        holders.extend(page.into_iter().filter(|member| member.roles.contains(&RoleId::new(role_id))));
+        if last_page {
+            break;
+        }
+    }
+
+    Ok(holders)
+}
+
+/// Swaps a member's chapter, faction and flavour roles from one chapter to the other and refreshes their nickname.
+async fn move_member(ctx: &Context, config: &Config, member: &Member, from: &Chapter, into: &Chapter) -> Result<(), serenity::Error> {
+    let record = MemberJoinMessage::get_message_by_discord_user_id(member.guild_id.to_string(), member.user.id.to_string()).await.ok();
+    let faction = record.as_ref().and_then(|record| record.faction);
+
+    if into.role_id != 0 {
+        member.add_role(&ctx.http, RoleId::new(into.role_id)).await?;
+    }
+    if let Some(faction_role_id) = into.faction_role_id(faction) {
+        member.add_role(&ctx.http, RoleId::new(faction_role_id)).await?;
+    }
+    if from.flavour != into.flavour && let Some(flavour_role_id) = config.flavour_role_id(into.flavour) {
+        member.add_role(&ctx.http, RoleId::new(flavour_role_id)).await?;
+    }
+
+    member.remove_role(&ctx.http, RoleId::new(from.role_id)).await?;
+    if let Some(faction_role_id) = from.faction_role_id(faction).filter(|role_id| into.faction_role_id(faction) != Some(*role_id)) {
+        member.remove_role(&ctx.http, RoleId::new(faction_role_id)).await?;
+    }
+    if from.flavour != into.flavour && let Some(flavour_role_id) = config.flavour_role_id(from.flavour) {
+        member.remove_role(&ctx.http, RoleId::new(flavour_role_id)).await?;
+    }
+
+    if let Some(record) = record.filter(|record| record.chapter_name == from.name) {
+        apply_nickname_policy(ctx, member, &record.character_name, &record.realm_name, &into.name).await;
+    }
+
+    Ok(())
+}
+
+fn not_saved(templates: &Templates, error: String) -> String {
+    println!("Error saving chapters: {}", error);
+    templates.text("chapters_not_saved", &[("error", error)])
+}
+
+async fn reply_not_saved(ctx: &Context, command: &CommandInteraction, templates: &Templates, error: String) -> Result<(), serenity::Error> {
+    command.create_response(
+        &ctx.http,
+        CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::default()
+                .ephemeral(true)
+                .content(not_saved(templates, error))
+        ),
+    ).await
+}
+
+/// Joins lines until `max_length`, then notes how many were left out.
+pub(crate) fn join_lines(lines: &[String], max_length: usize) -> String {
+    let mut joined = String::new();
+    for (index, line) in lines.iter().enumerate() {
+        if joined.len() + line.len() + 1 > max_length {
+            joined += &format!("… and {} more", lines.len() - index);
+            break;
+        }
+        joined += line;
+        joined += "\n";
+    }
+    joined
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn realm_from_pattern_keeps_realm_as_written() {
+        assert_eq!(realm_from_pattern("Chapter - {realm}", "Chapter - Area 52"), Some("Area 52".to_string()));
+        assert_eq!(realm_from_pattern("{realm} (EU)", "Kel'Thuzad (EU)"), Some("Kel'Thuzad".to_string()));
+        assert_eq!(realm_from_pattern("[{realm}]", "[ Stormrage ]"), Some("Stormrage".to_string()));
+    }
+
+    #[test]
+    fn realm_from_pattern_rejects_other_roles() {
+        assert_eq!(realm_from_pattern("Chapter - {realm}", "Officer - Area 52"), None);
+        assert_eq!(realm_from_pattern("{realm} (EU)", "Area 52 (US)"), None);
+        assert_eq!(realm_from_pattern("Chapter - {realm}", "Chapter - "), None);
+        assert_eq!(realm_from_pattern("Chapter", "Chapter"), None);
+    }
+
+    #[test]
+    fn normalize_ignores_case_and_punctuation() {
+        assert_eq!(normalize("Area 52"), normalize("area52"));
+        assert_eq!(normalize("Kel'Thuzad"), normalize("KelThuzad"));
+        assert_ne!(normalize("Area 52"), normalize("Area 53"));
+    }
+}
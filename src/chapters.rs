@@ -2,60 +2,226 @@ use std::fmt::Debug;
 use std::collections::HashMap;
 use std::sync::RwLock;
 use serde::{Deserialize, Serialize};
+use serenity::all::GuildId;
+use crate::config::{write_atomically, Config};
 
-static CHAPTERS_CACHE: RwLock<Option<Chapters>> = RwLock::new(None);
+// Chapter lists keyed by guild id.
+static CHAPTERS_CACHE: RwLock<Option<HashMap<u64, Chapters>>> = RwLock::new(None);
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// WoW game version a chapter plays. Realm names repeat across versions, so a realm only means
+/// something together with its flavour.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Flavour {
+    #[default]
+    Retail,
+    Classic,
+    Hardcore,
+}
+
+impl Flavour {
+    pub const ALL: [Flavour; 3] = [Flavour::Retail, Flavour::Classic, Flavour::Hardcore];
+
+    pub fn key(&self) -> &'static str {
+        match self {
+            Flavour::Retail => "retail",
+            Flavour::Classic => "classic",
+            Flavour::Hardcore => "hardcore",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Flavour> {
+        Self::ALL.into_iter().find(|flavour| flavour.key() == key)
+    }
+
+    /// Template string holding the flavour's display name.
+    pub fn label_key(&self) -> String {
+        format!("flavour_{}", self.key())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Faction {
+    Alliance,
+    Horde,
+}
+
+impl Faction {
+    pub const ALL: [Faction; 2] = [Faction::Alliance, Faction::Horde];
+
+    pub fn key(&self) -> &'static str {
+        match self {
+            Faction::Alliance => "alliance",
+            Faction::Horde => "horde",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Faction> {
+        Self::ALL.into_iter().find(|faction| faction.key() == key)
+    }
+
+    /// Template string holding the faction's display name.
+    pub fn label_key(&self) -> String {
+        format!("faction_{}", self.key())
+    }
+}
+
+/// Whether a chapter takes new members. Members who pick a chapter that does not go on its waitlist.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChapterStatus {
+    #[default]
+    Open,
+    /// Hidden from the welcome list.
+    Closed,
+    /// Listed, but every new member is queued.
+    Waitlist,
+}
+
+impl ChapterStatus {
+    pub const ALL: [ChapterStatus; 3] = [ChapterStatus::Open, ChapterStatus::Closed, ChapterStatus::Waitlist];
+
+    pub fn key(&self) -> &'static str {
+        match self {
+            ChapterStatus::Open => "open",
+            ChapterStatus::Closed => "closed",
+            ChapterStatus::Waitlist => "waitlist",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<ChapterStatus> {
+        Self::ALL.into_iter().find(|status| status.key() == key)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Chapter {
     pub name: String,
     pub role_id: u64,
+    /// Game version the chapter plays. Chapters without one are Retail.
+    #[serde(default)]
+    pub flavour: Flavour,
+    /// Role of the chapter's GM. When set, only this role and global admins may act on the chapter's cards.
+    #[serde(default)]
+    pub officer_role_id: Option<u64>,
+    /// Channel where new chapter members are welcomed.
+    #[serde(default)]
+    pub announcement_channel_id: Option<u64>,
+    /// User id of the chapter's GM, shown to new members as their contact.
+    #[serde(default)]
+    pub gm_user_id: Option<u64>,
+    /// Chapter-specific text added to the welcome post and the member's DM.
+    #[serde(default)]
+    pub welcome_blurb: Option<String>,
+    /// Extra role per faction for chapters that run an Alliance and a Horde guild on the same realm.
+    /// Members of a split chapter pick their faction and get its role on top of `role_id`.
+    #[serde(default)]
+    pub faction_role_ids: HashMap<Faction, u64>,
+    /// Archived chapters stay in the file for history, keeping their number, but are no longer offered to members.
+    #[serde(default)]
+    pub archived: bool,
+    /// Chapter this one was merged into, when it was archived by a merge.
+    #[serde(default)]
+    pub merged_into: Option<String>,
+    #[serde(default)]
+    pub status: ChapterStatus,
+    /// Most members the chapter takes. Once reached, new members are queued as if it were on waitlist.
+    #[serde(default)]
+    pub member_cap: Option<u32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Chapters {
     pub chapters: Vec<Chapter>,
+    /// Guild these chapters belong to. Not stored in the file; each guild has its own file.
+    #[serde(skip)]
+    pub guild_id: u64,
 }
 
 impl Chapters {
-    pub fn load() -> Self {
+    pub fn for_guild(guild_id: GuildId) -> Self {
         // Try to read from cache first.
-        if let Ok(cache) = CHAPTERS_CACHE.read() {
-            if let Some(chapters) = cache.as_ref() {
-                return chapters.clone();
-            }
+        if let Ok(cache) = CHAPTERS_CACHE.read() && let Some(chapters) = cache.as_ref().and_then(|guilds| guilds.get(&guild_id.get())) {
+            return chapters.clone();
         }
 
-        // Cache miss - load from disk.
-        let file = std::fs::File::open("chapters.json").expect("chapters.json not found");
-        let chapters: Chapters = serde_json::from_reader(file).expect("chapters.json not valid");
+        // Cache miss - load from disk. A guild without a usable chapter file has no chapters; the self-check reports it.
+        let chapters = Self::read_from_disk(guild_id).unwrap_or_else(|e| {
+            println!("{}. Guild {} has no chapters until it is fixed.", e, guild_id);
+            Chapters { chapters: Vec::new(), guild_id: guild_id.get() }
+        });
+        chapters.replace_cache();
 
-        // Update cache.
+        chapters
+    }
+
+    /// Reads the guild's chapter file without touching the cache.
+    pub fn read_from_disk(guild_id: GuildId) -> Result<Self, String> {
+        let path = Config::chapters_path(guild_id);
+        let file = std::fs::File::open(&path).map_err(|_| format!("{} not found", path))?;
+        let mut chapters: Chapters = serde_json::from_reader(file).map_err(|e| format!("{} not valid: {}", path, e))?;
+        chapters.guild_id = guild_id.get();
+
+        Ok(chapters)
+    }
+
+    /// Names used by more than one chapter, ignoring case. Members are stored by chapter name, so these clash.
+    pub fn duplicate_names(&self) -> Vec<String> {
+        let mut seen = std::collections::HashSet::new();
+        self.chapters.iter()
+            .filter(|chapter| !seen.insert(chapter.name.to_lowercase()))
+            .map(|chapter| chapter.name.clone())
+            .collect()
+    }
+
+    /// Makes these the chapters every later `for_guild` returns for their guild.
+    pub fn replace_cache(&self) {
         if let Ok(mut cache) = CHAPTERS_CACHE.write() {
-            *cache = Some(chapters.clone());
+            cache.get_or_insert_with(HashMap::new).insert(self.guild_id, self.clone());
         }
-
-        chapters
     }
 
-    pub fn add_chapter(&mut self, chapter: Chapter) {
+    pub fn add_chapter(&mut self, chapter: Chapter) -> Result<(), String> {
         self.chapters.push(chapter);
         self.sort();
-        self.save();
+        self.save()
+    }
+
+    /// Takes a chapter out of use. It is kept rather than removed so member records and chapter numbers stay valid.
+    pub fn archive_chapter(&mut self, id: usize, merged_into: Option<String>) -> Result<(), String> {
+        if let Some(chapter) = self.chapters.get_mut(id) {
+            chapter.archived = true;
+            chapter.merged_into = merged_into;
+        }
+        self.save()
+    }
+
+    pub fn set_recruitment(&mut self, id: usize, status: ChapterStatus, member_cap: Option<u32>) -> Result<(), String> {
+        if let Some(chapter) = self.chapters.get_mut(id) {
+            chapter.status = status;
+            chapter.member_cap = member_cap;
+        }
+        self.save()
     }
 
-    pub fn remove_chapter(&mut self, id: u8) {
-        self.chapters.remove(id as usize);
-        self.save();
+    pub fn rename_chapter(&mut self, id: usize, name: String) -> Result<(), String> {
+        if let Some(chapter) = self.chapters.get_mut(id) {
+            chapter.name = name;
+        }
+        self.save()
     }
 
-    pub fn save(&mut self) {
-        let file = std::fs::File::create("../chapters.json").expect("Could not create chapters.json");
-        serde_json::to_writer_pretty(file, &self.chapters).expect("Could not write to chapters.json");
+    /// Writes the guild's chapter file. The cache only changes once the file is written.
+    pub fn save(&mut self) -> Result<(), String> {
+        let path = Config::chapters_path(GuildId::new(self.guild_id));
+        let contents = serde_json::to_string_pretty(&self).map_err(|e| e.to_string())?;
+        write_atomically(&path, contents.as_bytes()).map_err(|e| format!("Could not write {}: {}", path, e))?;
 
         // Update cache with new values.
-        if let Ok(mut cache) = CHAPTERS_CACHE.write() {
-            *cache = Some(self.clone());
-        }
+        self.replace_cache();
+
+        Ok(())
     }
 
     fn sort(&mut self) {
@@ -71,6 +237,17 @@ impl Chapters {
         self.chapters.iter().find(|chapter| chapter.name == name)
     }
 
+    /// Position of a chapter named as an admin typed it, ignoring case.
+    pub fn find_id_by_name(&self, name: &str) -> Option<usize> {
+        let name = name.trim().to_lowercase();
+        self.chapters.iter().position(|chapter| chapter.name.to_lowercase() == name)
+    }
+
+    /// A chapter members may still pick, by its number in the list.
+    pub fn get_active_by_id(&self, id: usize) -> Option<&Chapter> {
+        self.get_by_id(id).filter(|chapter| !chapter.archived)
+    }
+
     pub fn get_id_by_name(&self, name: &str) -> Option<u8> {
         //self.by_name.get(name).map(|&index| index as u8)
         self.chapters.iter().position(|chapter| chapter.name == name).map(|index| index as u8)
@@ -84,15 +261,37 @@ impl Chapters {
         &self.chapters
     }
 
+    /// Flavours played by at least one chapter, in a fixed order.
+    pub fn flavours(&self) -> Vec<Flavour> {
+        Flavour::ALL.into_iter()
+            .filter(|flavour| self.chapters.iter().any(|chapter| !chapter.archived && chapter.flavour == *flavour))
+            .collect()
+    }
+
     pub fn to_formatted_list(&self) -> String {
+        Self::format_list(self.chapters.iter().enumerate())
+    }
+
+    /// The list for one flavour. Chapters keep their numbers from the full list.
+    pub fn to_formatted_list_for(&self, flavour: Flavour) -> String {
+        Self::format_list(self.chapters.iter().enumerate().filter(|(_, chapter)| chapter.flavour == flavour))
+    }
+
+    fn format_list<'a>(chapters: impl Iterator<Item = (usize, &'a Chapter)>) -> String {
         let mut result = String::from("Available Chapters:\n\n```");
         let mut num = 1;
         let num_pad = 2;
+        let mut has_waitlist = false;
 
-        for (id, chapter) in self.chapters.iter().enumerate() {
-            let chars = chapter.name.chars().count();
-            result.push_str(&format!("[{}] {}", id, chapter.name));
-            let pad = 16 - chars;
+        for (id, chapter) in chapters.filter(|(_, chapter)| !chapter.archived && chapter.status != ChapterStatus::Closed) {
+            let mut name = chapter.name.clone();
+            if chapter.status == ChapterStatus::Waitlist {
+                name.push('*');
+                has_waitlist = true;
+            }
+            let chars = name.chars().count();
+            result.push_str(&format!("[{}] {}", id, name));
+            let pad = 16usize.saturating_sub(chars);
             if pad > 0 && num % num_pad != 0 {
                 result.push_str(&" ".repeat(pad));
             }
@@ -105,6 +304,9 @@ impl Chapters {
             num += 1;
         }
         result.push_str("\n```");
+        if has_waitlist {
+            result.push_str("\n\\* Waitlist only");
+        }
         result
     }
 }
@@ -113,6 +315,82 @@ impl Chapter {
     pub fn get_role_id(&self) -> u64 {
         self.role_id.clone()
     }
+
+    pub fn is_split(&self) -> bool {
+        !self.factions().is_empty()
+    }
+
+    /// Factions the chapter has a role for, in a fixed order.
+    pub fn factions(&self) -> Vec<Faction> {
+        Faction::ALL.into_iter()
+            .filter(|faction| self.faction_role_id(Some(*faction)).is_some())
+            .collect()
+    }
+
+    pub fn faction_role_id(&self, faction: Option<Faction>) -> Option<u64> {
+        faction.and_then(|faction| self.faction_role_ids.get(&faction).copied()).filter(|role_id| *role_id != 0)
+    }
 }
 
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chapter(name: &str, flavour: Flavour, status: ChapterStatus) -> Chapter {
+        Chapter { name: name.to_string(), flavour, status, ..Default::default() }
+    }
+
+    fn chapters() -> Chapters {
+        let mut archived = chapter("Illidan", Flavour::Retail, ChapterStatus::Open);
+        archived.archived = true;
+        Chapters {
+            chapters: vec![
+                chapter("Area 52", Flavour::Retail, ChapterStatus::Open),
+                chapter("Whitemane", Flavour::Classic, ChapterStatus::Open),
+                chapter("Stormrage", Flavour::Retail, ChapterStatus::Waitlist),
+                chapter("Tichondrius", Flavour::Retail, ChapterStatus::Closed),
+                archived,
+            ],
+            guild_id: 0,
+        }
+    }
+
+    #[test]
+    fn format_list_hides_closed_and_archived_chapters() {
+        let list = chapters().to_formatted_list();
+        assert!(list.contains("[0] Area 52"));
+        assert!(list.contains("[1] Whitemane"));
+        assert!(!list.contains("Tichondrius"));
+        assert!(!list.contains("Illidan"));
+    }
+
+    #[test]
+    fn format_list_marks_waitlisted_chapters() {
+        let list = chapters().to_formatted_list();
+        assert!(list.contains("[2] Stormrage*"));
+        assert!(list.ends_with("\\* Waitlist only"));
+
+        let open_only = Chapters { chapters: vec![chapter("Area 52", Flavour::Retail, ChapterStatus::Open)], guild_id: 0 };
+        assert!(!open_only.to_formatted_list().contains("Waitlist only"));
+    }
+
+    #[test]
+    fn format_list_for_flavour_keeps_full_list_numbers() {
+        let classic = chapters().to_formatted_list_for(Flavour::Classic);
+        assert!(classic.contains("[1] Whitemane"));
+        assert!(!classic.contains("Area 52"));
+        assert!(!classic.contains("Stormrage"));
+
+        let retail = chapters().to_formatted_list_for(Flavour::Retail);
+        assert!(retail.contains("[0] Area 52"));
+        assert!(retail.contains("[2] Stormrage*"));
+        assert!(!retail.contains("Whitemane"));
+    }
+
+    #[test]
+    fn flavours_lists_only_flavours_with_active_chapters() {
+        assert_eq!(chapters().flavours(), vec![Flavour::Retail, Flavour::Classic]);
+    }
+}
@@ -0,0 +1,255 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serenity::all::GuildId;
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::RwLock;
+use crate::chapters::Flavour;
+use crate::permissions::Action;
+
+static CONFIG_CACHE: RwLock<Option<Config>> = RwLock::new(None);
+
+// Every setting can be overridden with an environment variable made of this prefix and the
+// field name in capitals, e.g. NMI_GUILD_ID or NMI_NICKNAME_TEMPLATE.
+const ENV_PREFIX: &str = "NMI_";
+// Path of the config file. Defaults to config.json in the working directory.
+const CONFIG_PATH_VAR: &str = "NMI_CONFIG";
+const DEFAULT_CONFIG_PATH: &str = "config.json";
+// Older installs kept these settings in secrets.json next to the token.
+const LEGACY_CONFIG_PATH: &str = "secrets.json";
+
+/// Non-secret settings. Safe to commit or bake into an image; the token lives in `Secrets`.
+/// The top level configures the primary guild; any further guilds are listed under `guilds`.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Config {
+    pub guild_id: u64,
+    /// Chapter list for this guild. Defaults to chapters.json for the primary guild
+    /// and chapters.<guild_id>.json for the others.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chapters_path: Option<String>,
+
+    /// Users allowed to do everything, regardless of their roles.
+    pub authorized_ids: Vec<u64>,
+    /// Role ids allowed to perform each officer action. Admin roles may perform all of them.
+    #[serde(default)]
+    pub permissions: HashMap<Action, Vec<u64>>,
+
+    pub nmi_channel_id: u64,
+    pub welcome_channel_id: u64,
+    pub new_member_role_id: u64,
+    pub guest_role_id: u64,
+    pub member_role_id: u64,
+    /// Role granted with the chapter role for each game flavour, e.g. {"classic": 123}. Flavours without one get no role.
+    #[serde(default)]
+    pub flavour_role_ids: HashMap<Flavour, u64>,
+    /// Chapter each invite code recruits for, e.g. {"AbCd123": "Area52"}. Members joining through one
+    /// find that chapter already filled in on the form.
+    #[serde(default)]
+    pub invite_chapters: HashMap<String, String>,
+    /// Channel for the startup configuration report. The report is only printed when unset.
+    #[serde(default)]
+    pub admin_channel_id: Option<u64>,
+
+    /// Nickname applied to members after onboarding, e.g. "{character} ({chapter})".
+    /// Supports {character}, {realm} and {chapter}. Nicknames are left alone when unset.
+    #[serde(default)]
+    pub nickname_template: Option<String>,
+
+    /// Locale for shared messages such as the welcome embed and officer cards, e.g. "en-US".
+    /// Ephemeral replies, modals and DMs follow each member's own Discord locale instead.
+    #[serde(default)]
+    pub default_locale: Option<String>,
+
+    /// Settings for every other guild the bot serves, each laid out like the top level.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub guilds: Vec<Config>,
+}
+
+impl Config {
+    pub fn load() -> Config {
+        // Try to read from cache first.
+        if let Ok(cache) = CONFIG_CACHE.read() && let Some(config) = cache.as_ref() {
+            return config.clone();
+        }
+
+        // Cache miss - load from disk.
+        let config = Self::read_from_disk().unwrap_or_else(|e| panic!("{}", e));
+        config.replace_cache();
+
+        config
+    }
+
+    /// Makes this the config every later `load` returns.
+    pub fn replace_cache(&self) {
+        if let Ok(mut cache) = CONFIG_CACHE.write() {
+            *cache = Some(self.clone());
+        }
+    }
+
+    /// Settings for one guild. Guilds that are not configured get the primary guild's settings,
+    /// but events from them are dropped before they reach a handler (see `is_configured`).
+    pub fn for_guild(guild_id: GuildId) -> Config {
+        Self::load().guild(guild_id)
+    }
+
+    /// One guild's settings out of this config, without the `guilds` list.
+    pub fn guild(&self, guild_id: GuildId) -> Config {
+        let mut guild_config = self.guilds.iter()
+            .find(|guild| guild.guild_id == guild_id.get())
+            .unwrap_or(self)
+            .clone();
+        guild_config.guilds.clear();
+        guild_config
+    }
+
+    pub fn guild_ids() -> Vec<GuildId> {
+        Self::load().all_guild_ids()
+    }
+
+    /// The primary guild followed by every guild under `guilds`.
+    pub fn all_guild_ids(&self) -> Vec<GuildId> {
+        std::iter::once(self.guild_id)
+            .chain(self.guilds.iter().map(|guild| guild.guild_id))
+            .map(GuildId::new)
+            .collect()
+    }
+
+    pub fn is_configured(guild_id: GuildId) -> bool {
+        Self::guild_ids().contains(&guild_id)
+    }
+
+    /// The guild an interaction came from. Interactions outside a guild, such as DM buttons, belong to the primary guild.
+    pub fn guild_or_primary(guild_id: Option<GuildId>) -> GuildId {
+        guild_id.unwrap_or_else(|| GuildId::new(Self::load().guild_id))
+    }
+
+    pub fn is_primary(guild_id: GuildId) -> bool {
+        Self::load().guild_id == guild_id.get()
+    }
+
+    pub fn chapters_path(guild_id: GuildId) -> String {
+        match Self::for_guild(guild_id).chapters_path {
+            Some(path) => path,
+            None if Self::is_primary(guild_id) => "chapters.json".to_string(),
+            None => format!("chapters.{}.json", guild_id),
+        }
+    }
+
+    /// Rules are versioned per guild, like the acceptances recorded against them.
+    pub fn rules_path(guild_id: GuildId) -> String {
+        if Self::is_primary(guild_id) {
+            "rules.json".to_string()
+        } else {
+            format!("rules.{}.json", guild_id)
+        }
+    }
+
+    pub fn flavour_role_id(&self, flavour: Flavour) -> Option<u64> {
+        self.flavour_role_ids.get(&flavour).copied().filter(|role_id| *role_id != 0)
+    }
+
+    pub fn path() -> String {
+        std::env::var(CONFIG_PATH_VAR).unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_string())
+    }
+
+    /// Reads the config file with environment overrides applied, without touching the cache.
+    pub fn read_from_disk() -> Result<Config, String> {
+        Self::from_file_value(Self::read_file_value()?)
+    }
+
+    /// The config file as written, before environment overrides.
+    fn read_file_value() -> Result<Value, String> {
+        let path = Self::path();
+        let file = match std::fs::File::open(&path) {
+            Ok(file) => file,
+            Err(_) if std::env::var(CONFIG_PATH_VAR).is_err() => {
+                let file = std::fs::File::open(LEGACY_CONFIG_PATH)
+                    .map_err(|_| format!("{} not found. Copy config.example.json or set {}.", path, CONFIG_PATH_VAR))?;
+                println!("{} not found, reading settings from {}. Move them to {} when convenient.", path, LEGACY_CONFIG_PATH, path);
+                file
+            }
+            Err(e) => return Err(format!("Could not open {}: {}", path, e)),
+        };
+
+        let mut value: Value = serde_json::from_reader(file).map_err(|e| format!("{} not valid: {}", path, e))?;
+        // Never carry the token over from a legacy secrets.json into the config file.
+        if let Some(fields) = value.as_object_mut() {
+            fields.remove("token");
+        }
+
+        Ok(value)
+    }
+
+    fn from_file_value(mut value: Value) -> Result<Config, String> {
+        apply_env_overrides(&mut value, std::env::vars());
+
+        serde_json::from_value(value).map_err(|e| format!("{} not valid after environment overrides: {}", Self::path(), e))
+    }
+
+    /// Changes one of a guild's settings in the config file and the cache, returning its previous value.
+    /// The file is rewritten from its own contents, so environment overrides never end up in it.
+    pub fn set(guild_id: GuildId, setting: &str, new_value: Value) -> Result<Value, String> {
+        let mut file_value = Self::read_file_value()?;
+        let guild_fields = find_guild_fields(&mut file_value, guild_id)
+            .ok_or(format!("Guild {} is not configured in {}", guild_id, Self::path()))?;
+        let old_value = guild_fields.insert(setting.to_string(), new_value).unwrap_or(Value::Null);
+
+        let config = Self::from_file_value(file_value.clone())?;
+        let contents = serde_json::to_string_pretty(&file_value).map_err(|e| e.to_string())?;
+        write_atomically(&Self::path(), contents.as_bytes()).map_err(|e| format!("Could not write {}: {}", Self::path(), e))?;
+        config.replace_cache();
+
+        Ok(old_value)
+    }
+
+    /// The environment variable that overrides a setting, if it is set.
+    pub fn env_override(setting: &str) -> Option<String> {
+        let name = format!("{}{}", ENV_PREFIX, setting.to_uppercase());
+        std::env::var(&name).ok().map(|_| name)
+    }
+}
+
+/// The object holding a guild's settings: the top level for the primary guild, or its entry under `guilds`.
+fn find_guild_fields(file_value: &mut Value, guild_id: GuildId) -> Option<&mut serde_json::Map<String, Value>> {
+    let is_guild = |fields: &serde_json::Map<String, Value>| fields.get("guild_id").and_then(Value::as_u64) == Some(guild_id.get());
+
+    let fields = file_value.as_object_mut()?;
+    if is_guild(fields) {
+        return Some(fields);
+    }
+
+    fields.get_mut("guilds")?.as_array_mut()?
+        .iter_mut()
+        .filter_map(Value::as_object_mut)
+        .find(|guild_fields| is_guild(guild_fields))
+}
+
+/// Writes to a temporary file next to the target and renames it over the target,
+/// so a crash mid-write never leaves a truncated file behind.
+pub(crate) fn write_atomically(path: &str, contents: &[u8]) -> std::io::Result<()> {
+    let temp_path = format!("{}.tmp", path);
+    let mut file = std::fs::File::create(&temp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    std::fs::rename(&temp_path, path)
+}
+
+/// Sets each NMI_<FIELD> variable on the matching field of the primary guild. Values that parse as JSON are used as
+/// such, so NMI_GUILD_ID=123 is a number and NMI_AUTHORIZED_IDS=[1,2] a list; anything else is a string.
+fn apply_env_overrides(value: &mut Value, vars: impl Iterator<Item = (String, String)>) {
+    let Some(fields) = value.as_object_mut() else {
+        return;
+    };
+
+    for (name, raw) in vars {
+        if name == CONFIG_PATH_VAR || crate::secrets::TOKEN_VARS.contains(&name.as_str()) {
+            continue;
+        }
+        let Some(field) = name.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+
+        let parsed = serde_json::from_str(&raw).unwrap_or(Value::String(raw));
+        fields.insert(field.to_lowercase(), parsed);
+    }
+}
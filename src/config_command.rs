@@ -0,0 +1,381 @@
+use serde::Serialize;
+use serde_json::Value;
+use serenity::all::{ChannelType, CommandInteraction, CommandOptionType, CreateCommand, CreateCommandOption, ResolvedValue};
+use serenity::builder::{CreateEmbed, CreateInteractionResponse, CreateInteractionResponseMessage};
+use serenity::client::Context;
+use serenity::model::Timestamp;
+use crate::chapter_command::{join_lines, MAX_REPLY_LENGTH};
+use crate::chapters::Chapters;
+use crate::config::Config;
+use crate::member_db::ConfigChange;
+use crate::message_command;
+use crate::permissions::{authorize_command, Action};
+use crate::templates::Templates;
+
+// Settings that `/config channel` and `/config role` may change.
+const CHANNEL_SETTINGS: [&str; 3] = ["nmi_channel_id", "welcome_channel_id", "admin_channel_id"];
+const ROLE_SETTINGS: [&str; 3] = ["new_member_role_id", "guest_role_id", "member_role_id"];
+
+pub async fn register_config_command() -> CreateCommand {
+    let reload = CreateCommandOption::new(CommandOptionType::SubCommand, "reload", "Reload config.json and the chapter files from disk without restarting.");
+
+    let view = CreateCommandOption::new(CommandOptionType::SubCommand, "view", "Show the current channels, roles and authorized users.");
+
+    let mut channel_setting = CreateCommandOption::new(CommandOptionType::String, "setting", "Which channel to set.")
+        .required(true);
+    for setting in CHANNEL_SETTINGS {
+        channel_setting = channel_setting.add_string_choice(setting, setting);
+    }
+    let channel = CreateCommandOption::new(CommandOptionType::SubCommand, "channel", "Set one of the bot's channels.")
+        .add_sub_option(channel_setting)
+        .add_sub_option(CreateCommandOption::new(CommandOptionType::Channel, "channel", "The channel to use.")
+            .required(true)
+            .channel_types(vec![ChannelType::Text]));
+
+    let mut role_setting = CreateCommandOption::new(CommandOptionType::String, "setting", "Which role to set.")
+        .required(true);
+    for setting in ROLE_SETTINGS {
+        role_setting = role_setting.add_string_choice(setting, setting);
+    }
+    let role = CreateCommandOption::new(CommandOptionType::SubCommand, "role", "Set one of the roles the bot assigns.")
+        .add_sub_option(role_setting)
+        .add_sub_option(CreateCommandOption::new(CommandOptionType::Role, "role", "The role to use.")
+            .required(true));
+
+    let authorize = CreateCommandOption::new(CommandOptionType::SubCommand, "authorize", "Let a user do everything, regardless of roles.")
+        .add_sub_option(CreateCommandOption::new(CommandOptionType::User, "user", "The user to authorize.").required(true));
+    let deauthorize = CreateCommandOption::new(CommandOptionType::SubCommand, "deauthorize", "Remove a user from the authorized users.")
+        .add_sub_option(CreateCommandOption::new(CommandOptionType::User, "user", "The user to remove.").required(true));
+
+    CreateCommand::new("config").description("Manage the bot's configuration.")
+        .add_option(view)
+        .add_option(channel)
+        .add_option(role)
+        .add_option(authorize)
+        .add_option(deauthorize)
+        .add_option(reload)
+}
+
+pub async fn handle_config_command(ctx: &Context, command: &CommandInteraction) -> Result<(), serenity::Error> {
+    let subcommand = command.data.options().first().map(|option| option.name).unwrap_or_default();
+
+    match subcommand {
+        "reload" => handle_reload(ctx, command).await,
+        "view" => handle_view(ctx, command).await,
+        "channel" | "role" | "authorize" | "deauthorize" => handle_set(ctx, command).await,
+        _ => Ok(()),
+    }
+}
+
+async fn handle_view(ctx: &Context, command: &CommandInteraction) -> Result<(), serenity::Error> {
+    if !authorize_command(ctx, command, Action::Admin).await? {
+        return Ok(());
+    }
+
+    let config = Config::for_guild(Config::guild_or_primary(command.guild_id));
+    let channel = |channel_id: u64| format!("<#{}>", channel_id);
+    let role = |role_id: u64| format!("<@&{}>", role_id);
+    let authorized = config.authorized_ids.iter()
+        .map(|user_id| format!("<@{}>", user_id))
+        .collect::<Vec<String>>()
+        .join(", ");
+
+    let embed = CreateEmbed::new()
+        .title(Config::path())
+        .field("nmi_channel_id", channel(config.nmi_channel_id), true)
+        .field("welcome_channel_id", channel(config.welcome_channel_id), true)
+        .field("admin_channel_id", config.admin_channel_id.map(channel).unwrap_or("-".to_string()), true)
+        .field("new_member_role_id", role(config.new_member_role_id), true)
+        .field("guest_role_id", role(config.guest_role_id), true)
+        .field("member_role_id", role(config.member_role_id), true)
+        .field("authorized_ids", if authorized.is_empty() { "-".to_string() } else { authorized }, false);
+
+    command.create_response(
+        &ctx.http,
+        CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::default()
+                .ephemeral(true)
+                .embed(embed)
+        ),
+    ).await?;
+
+    Ok(())
+}
+
+async fn handle_set(ctx: &Context, command: &CommandInteraction) -> Result<(), serenity::Error> {
+    if !authorize_command(ctx, command, Action::Admin).await? {
+        return Ok(());
+    }
+
+    let templates = Templates::for_locale(&command.locale);
+    let guild_id = Config::guild_or_primary(command.guild_id);
+    let config = Config::for_guild(guild_id);
+
+    let mut subcommand = "";
+    let mut setting = String::new();
+    let mut id = 0;
+    for option in command.data.options() {
+        if let ResolvedValue::SubCommand(sub_options) = option.value {
+            subcommand = option.name;
+            for sub_option in sub_options {
+                match (sub_option.name, sub_option.value) {
+                    ("setting", ResolvedValue::String(value)) => setting = value.to_string(),
+                    ("channel", ResolvedValue::Channel(channel)) => id = channel.id.get(),
+                    ("role", ResolvedValue::Role(role)) => id = role.id.get(),
+                    ("user", ResolvedValue::User(user, _)) => id = user.id.get(),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    let new_value = match subcommand {
+        "channel" if CHANNEL_SETTINGS.contains(&setting.as_str()) => Value::from(id),
+        "role" if ROLE_SETTINGS.contains(&setting.as_str()) => Value::from(id),
+        "authorize" | "deauthorize" => {
+            setting = "authorized_ids".to_string();
+            let mut authorized_ids = config.authorized_ids.clone();
+            authorized_ids.retain(|user_id| *user_id != id);
+            if subcommand == "authorize" {
+                authorized_ids.push(id);
+            }
+
+            // Refuse to lock everyone out of the admin commands.
+            let admin_roles = config.permissions.get(&Action::Admin).is_some_and(|role_ids| !role_ids.is_empty());
+            if authorized_ids.is_empty() && !admin_roles {
+                command.create_response(
+                    &ctx.http,
+                    CreateInteractionResponse::Message(
+                        CreateInteractionResponseMessage::default()
+                            .ephemeral(true)
+                            .content(templates.text("config_last_admin", &[]))
+                    ),
+                ).await?;
+                return Ok(());
+            }
+
+            Value::from(authorized_ids)
+        }
+        _ => return Ok(()),
+    };
+
+    let reply = match Config::set(guild_id, &setting, new_value.clone()) {
+        Ok(old_value) => {
+            println!("{} changed {} from {} to {}", command.user.id, setting, old_value, new_value);
+            let result = ConfigChange::record(
+                guild_id.to_string(),
+                command.user.id.to_string(),
+                setting.clone(),
+                old_value.to_string(),
+                new_value.to_string(),
+                Timestamp::now().to_string(),
+            ).await;
+            if let Err(e) = result {
+                println!("Error recording config change in database: {}", e);
+            }
+
+            let mut reply = templates.text("config_updated", &[("setting", setting.clone()), ("value", new_value.to_string())]);
+            if let Some(variable) = Config::env_override(&setting) {
+                reply += "\n";
+                reply += &templates.text("config_overridden", &[("variable", variable)]);
+            }
+            reply
+        }
+        Err(e) => {
+            println!("Error saving config: {}", e);
+            templates.text("config_save_failed", &[("setting", setting.clone()), ("error", e)])
+        }
+    };
+
+    command.create_response(
+        &ctx.http,
+        CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::default()
+                .ephemeral(true)
+                .content(reply)
+        ),
+    ).await?;
+
+    Ok(())
+}
+
+async fn handle_reload(ctx: &Context, command: &CommandInteraction) -> Result<(), serenity::Error> {
+    if !authorize_command(ctx, command, Action::Admin).await? {
+        return Ok(());
+    }
+
+    let templates = Templates::for_locale(&command.locale);
+    let mut lines = Vec::new();
+
+    // Each file is validated on its own. A bad file keeps its previous value and does not hold back the other.
+    let old_config = Config::load();
+    let config_result = Config::read_from_disk().and_then(|config| {
+        // Commands are registered per guild at startup.
+        if config.all_guild_ids() != old_config.all_guild_ids() {
+            return Err("guild ids cannot change without a restart".to_string());
+        }
+        Ok(config)
+    });
+    match config_result {
+        Ok(config) => {
+            let mut changes = Vec::new();
+            for guild_id in config.all_guild_ids() {
+                let prefix = if guild_id.get() == config.guild_id { String::new() } else { format!("{} ", guild_id) };
+                for change in diff_fields(&old_config.guild(guild_id), &config.guild(guild_id)) {
+                    changes.push(format!("{}{}", prefix, change));
+                }
+            }
+            lines.push(describe_changes(&templates, &Config::path(), changes));
+            config.replace_cache();
+        }
+        Err(e) => lines.push(templates.text("reload_invalid", &[("file", Config::path()), ("error", e)])),
+    }
+
+    let mut chapters_changed = false;
+    for guild_id in Config::guild_ids() {
+        let path = Config::chapters_path(guild_id);
+        let old_chapters = Chapters::for_guild(guild_id);
+        let chapters_result = Chapters::read_from_disk(guild_id).and_then(|chapters| {
+            match chapters.duplicate_names().first() {
+                Some(name) => Err(format!("more than one chapter is named {}", name)),
+                None => Ok(chapters),
+            }
+        });
+        match chapters_result {
+            Ok(chapters) => {
+                let changes = diff_chapters(&old_chapters, &chapters);
+                chapters_changed |= !changes.is_empty();
+                lines.push(describe_changes(&templates, &path, changes));
+                chapters.replace_cache();
+            }
+            Err(e) => lines.push(templates.text("reload_invalid", &[("file", path), ("error", e)])),
+        }
+    }
+
+    // The welcome message lists the chapters, so it goes stale with them.
+    if chapters_changed && let Err(e) = message_command::refresh_welcome_messages(ctx).await {
+        println!("Error refreshing welcome messages: {}", e);
+    }
+
+    println!("Configuration reloaded by {}:\n{}", command.user.id, lines.join("\n"));
+
+    // One blank line between files in the reply.
+    let lines = lines.into_iter().map(|line| line + "\n").collect::<Vec<String>>();
+
+    command.create_response(
+        &ctx.http,
+        CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::default()
+                .ephemeral(true)
+                .content(join_lines(&lines, MAX_REPLY_LENGTH))
+        ),
+    ).await?;
+
+    Ok(())
+}
+
+fn describe_changes(templates: &Templates, file: &str, changes: Vec<String>) -> String {
+    if changes.is_empty() {
+        return templates.text("reload_unchanged", &[("file", file.to_string())]);
+    }
+
+    // A single file may not crowd the others out of the reply.
+    templates.text("reload_changed", &[("file", file.to_string()), ("changes", join_lines(&changes, MAX_REPLY_LENGTH / 2))])
+}
+
+/// Lists the top-level fields that differ, as "field: old → new".
+fn diff_fields<T: Serialize>(old: &T, new: &T) -> Vec<String> {
+    let old = serde_json::to_value(old).unwrap_or_default();
+    let new = serde_json::to_value(new).unwrap_or_default();
+    let (Value::Object(old), Value::Object(new)) = (old, new) else {
+        return Vec::new();
+    };
+
+    let mut changes = new.iter()
+        .filter(|(name, value)| old.get(*name) != Some(value))
+        .map(|(name, value)| format!("{}: {} → {}", name, old.get(name).cloned().unwrap_or(Value::Null), value))
+        .collect::<Vec<String>>();
+    changes.sort();
+    changes
+}
+
+/// Chapters are matched by name, so a rename shows up as one removed and one added.
+fn diff_chapters(old: &Chapters, new: &Chapters) -> Vec<String> {
+    let mut changes = Vec::new();
+
+    for chapter in &new.chapters {
+        match old.get_by_name(&chapter.name) {
+            None => changes.push(format!("+ {}", chapter.name)),
+            Some(old_chapter) => {
+                for change in diff_fields(old_chapter, chapter) {
+                    changes.push(format!("~ {} {}", chapter.name, change));
+                }
+            }
+        }
+    }
+    for chapter in &old.chapters {
+        if new.get_by_name(&chapter.name).is_none() {
+            changes.push(format!("- {}", chapter.name));
+        }
+    }
+
+    // Numbers in the welcome list and the chapter form follow the order in the file.
+    let old_order = old.chapters.iter().map(|chapter| &chapter.name).collect::<Vec<&String>>();
+    let new_order = new.chapters.iter().map(|chapter| &chapter.name).collect::<Vec<&String>>();
+    if changes.is_empty() && old_order != new_order {
+        changes.push("chapter order changed".to_string());
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chapters::Chapter;
+
+    fn chapters(chapters: Vec<Chapter>) -> Chapters {
+        Chapters { chapters, guild_id: 0 }
+    }
+
+    fn chapter(name: &str, role_id: u64) -> Chapter {
+        Chapter { name: name.to_string(), role_id, ..Default::default() }
+    }
+
+    #[test]
+    fn diff_fields_lists_changed_fields_only() {
+        let old = chapter("Area 52", 1);
+        let new = chapter("Area 52", 2);
+        assert_eq!(diff_fields(&old, &new), vec!["role_id: 1 → 2".to_string()]);
+        assert!(diff_fields(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn diff_fields_is_sorted() {
+        let old = chapter("Area 52", 1);
+        let new = chapter("Stormrage", 2);
+        let changes = diff_fields(&old, &new);
+        assert_eq!(changes.len(), 2);
+        assert!(changes[0].starts_with("name: "));
+        assert!(changes[1].starts_with("role_id: "));
+    }
+
+    #[test]
+    fn diff_chapters_lists_added_removed_and_changed() {
+        let old = chapters(vec![chapter("Area 52", 1), chapter("Stormrage", 2)]);
+        let new = chapters(vec![chapter("Area 52", 3), chapter("Illidan", 4)]);
+        assert_eq!(diff_chapters(&old, &new), vec![
+            "~ Area 52 role_id: 1 → 3".to_string(),
+            "+ Illidan".to_string(),
+            "- Stormrage".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn diff_chapters_notices_reordering() {
+        let old = chapters(vec![chapter("Area 52", 1), chapter("Stormrage", 2)]);
+        let new = chapters(vec![chapter("Stormrage", 2), chapter("Area 52", 1)]);
+        assert_eq!(diff_chapters(&old, &new), vec!["chapter order changed".to_string()]);
+        assert!(diff_chapters(&old, &old).is_empty());
+    }
+}
@@ -0,0 +1,161 @@
+use serenity::all::{CommandInteraction, CommandType, CreateCommand, GuildId};
+use serenity::builder::{CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage, EditInteractionResponse};
+use serenity::client::Context;
+use crate::chapters::{Chapter, Chapters};
+use crate::config::Config;
+use crate::member_db::{MemberJoinMessage, MemberJoinMessageStage, WelcomeMessage};
+use crate::member_info::{complete_card, rebuild_card, reset_onboarding};
+use crate::permissions::{authorize_command_for_chapter, Action};
+use crate::templates::Templates;
+
+/// Apps menu entries for acting on a member, or on a member's card, without finding the card first.
+pub async fn register_context_menu_commands() -> Vec<CreateCommand> {
+    vec![
+        CreateCommand::new("Re-send onboarding form").kind(CommandType::User),
+        CreateCommand::new("Reset onboarding").kind(CommandType::User),
+        CreateCommand::new("Mark complete").kind(CommandType::User),
+        CreateCommand::new("Rebuild card from database").kind(CommandType::Message),
+    ]
+}
+
+/// DMs the member a link to the welcome message, for members who have not submitted the chapter form.
+pub async fn handle_resend_form(ctx: &Context, command: &CommandInteraction) -> Result<(), serenity::Error> {
+    let guild_id = command.guild_id.ok_or(serenity::Error::Other("No guild ID found"))?;
+    let user_id = command.data.target_id.ok_or(serenity::Error::Other("No target user found"))?.to_user_id();
+    let record = MemberJoinMessage::get_message_by_discord_user_id(guild_id.to_string(), user_id.to_string()).await.ok();
+    if !authorize_command_for_chapter(ctx, command, Action::Reset, record_chapter(guild_id, record.as_ref()).as_ref()).await? {
+        return Ok(());
+    }
+
+    let templates = Templates::for_locale(&command.locale);
+    let mut vars = vec![("member", format!("<@{}>", user_id))];
+    if let Some(record) = &record {
+        vars.push(("stage", templates.text(record.stage.label_key(), &[])));
+        // Sending the form again would not change anything until the member is reset.
+        if matches!(record.stage, MemberJoinMessageStage::Onboarding | MemberJoinMessageStage::Completed) {
+            return reply(ctx, command, templates.text("context_form_already_submitted", &vars)).await;
+        }
+    }
+
+    // The member reads this away from the guild, so it is in the guild's language.
+    let reminder = Templates::for_guild(guild_id).text("onboarding_form_reminder", &[("welcome", welcome_link(guild_id).await)]);
+    let text = match user_id.direct_message(&ctx.http, CreateMessage::new().content(reminder)).await {
+        Ok(_) => templates.text("context_form_resent", &vars),
+        Err(e) => {
+            println!("Error re-sending onboarding form to {}: {}", user_id, e);
+            templates.text("context_form_not_sent", &vars)
+        }
+    };
+
+    reply(ctx, command, text).await
+}
+
+/// Takes the member back to a fresh join, so they fill in the whole form again.
+pub async fn handle_reset_onboarding(ctx: &Context, command: &CommandInteraction) -> Result<(), serenity::Error> {
+    let guild_id = command.guild_id.ok_or(serenity::Error::Other("No guild ID found"))?;
+    let user_id = command.data.target_id.ok_or(serenity::Error::Other("No target user found"))?.to_user_id();
+    let templates = Templates::for_locale(&command.locale);
+    let vars = [("member", format!("<@{}>", user_id))];
+    let Ok(record) = MemberJoinMessage::get_message_by_discord_user_id(guild_id.to_string(), user_id.to_string()).await else {
+        return reply(ctx, command, templates.text("lookup_no_record", &vars)).await;
+    };
+    if !authorize_command_for_chapter(ctx, command, Action::Reset, record_chapter(guild_id, Some(&record)).as_ref()).await? {
+        return Ok(());
+    }
+
+    // Roles, answers and the card are all touched, which can take longer than an interaction may wait.
+    command.create_response(
+        &ctx.http,
+        CreateInteractionResponse::Defer(CreateInteractionResponseMessage::new().ephemeral(true)),
+    ).await?;
+
+    let text = match reset_onboarding(ctx, guild_id, &record).await {
+        Ok(_) => {
+            println!("{} reset the onboarding of {}", command.user.id, user_id);
+            templates.text("context_reset", &vars)
+        }
+        Err(e) => {
+            println!("Error resetting onboarding of {}: {}", user_id, e);
+            templates.text("context_reset_failed", &[("member", format!("<@{}>", user_id)), ("error", e.to_string())])
+        }
+    };
+    command.edit_response(&ctx.http, EditInteractionResponse::new().content(text)).await?;
+
+    Ok(())
+}
+
+/// Marks the member complete, as the Mark Complete button on their card does.
+pub async fn handle_mark_complete(ctx: &Context, command: &CommandInteraction) -> Result<(), serenity::Error> {
+    let guild_id = command.guild_id.ok_or(serenity::Error::Other("No guild ID found"))?;
+    let user_id = command.data.target_id.ok_or(serenity::Error::Other("No target user found"))?.to_user_id();
+    let templates = Templates::for_locale(&command.locale);
+    let member = format!("<@{}>", user_id);
+    let Ok(record) = MemberJoinMessage::get_message_by_discord_user_id(guild_id.to_string(), user_id.to_string()).await else {
+        return reply(ctx, command, templates.text("lookup_no_record", &[("member", member)])).await;
+    };
+    if !authorize_command_for_chapter(ctx, command, Action::Complete, record_chapter(guild_id, Some(&record)).as_ref()).await? {
+        return Ok(());
+    }
+
+    let vars = [
+        ("member", member),
+        ("stage", templates.text(record.stage.label_key(), &[])),
+    ];
+    if !matches!(record.stage, MemberJoinMessageStage::Onboarding) {
+        return reply(ctx, command, templates.text("context_not_awaiting_approval", &vars)).await;
+    }
+
+    complete_card(ctx, guild_id, &record, command.user.id).await?;
+    println!("{} marked {} complete", command.user.id, user_id);
+
+    reply(ctx, command, templates.text("context_completed", &vars)).await
+}
+
+/// Draws the selected card again from the member's record, undoing hand edits and restoring lost buttons.
+pub async fn handle_rebuild_card(ctx: &Context, command: &CommandInteraction) -> Result<(), serenity::Error> {
+    let guild_id = command.guild_id.ok_or(serenity::Error::Other("No guild ID found"))?;
+    let message_id = command.data.target_id.ok_or(serenity::Error::Other("No target message found"))?.to_message_id();
+    let templates = Templates::for_locale(&command.locale);
+    let Ok(record) = MemberJoinMessage::get_message_by_message_id(message_id.to_string()).await else {
+        return reply(ctx, command, templates.text("context_not_a_card", &[])).await;
+    };
+    if !authorize_command_for_chapter(ctx, command, Action::Complete, record_chapter(guild_id, Some(&record)).as_ref()).await? {
+        return Ok(());
+    }
+
+    rebuild_card(ctx, guild_id, &record).await?;
+
+    reply(ctx, command, templates.text("context_card_rebuilt", &[("member", format!("<@{}>", record.discord_user_id))])).await
+}
+
+/// The chapter a member's record puts them in, so its officers may act on them.
+fn record_chapter(guild_id: GuildId, record: Option<&MemberJoinMessage>) -> Option<Chapter> {
+    Chapters::for_guild(guild_id).get_by_name(&record?.chapter_name).cloned()
+}
+
+/// A link to the guild's latest tracked welcome message, or the welcome channel when none is tracked.
+async fn welcome_link(guild_id: GuildId) -> String {
+    let tracked = match WelcomeMessage::get_all().await {
+        Ok(messages) => messages.into_iter().rfind(|message| message.guild_id == guild_id.get()),
+        Err(e) => {
+            println!("Error getting welcome messages from database: {}", e);
+            None
+        }
+    };
+
+    match tracked {
+        Some(message) => format!("https://discord.com/channels/{}/{}/{}", guild_id, message.channel_id, message.message_id),
+        None => format!("<#{}>", Config::for_guild(guild_id).welcome_channel_id),
+    }
+}
+
+async fn reply(ctx: &Context, command: &CommandInteraction, text: String) -> Result<(), serenity::Error> {
+    command.create_response(
+        &ctx.http,
+        CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new()
+                .content(text)
+                .ephemeral(true)
+        ),
+    ).await
+}
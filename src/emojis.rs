@@ -2,14 +2,6 @@ pub fn emoji_check_mark() -> String {
     "✅".to_string()
 }
 
-pub fn emoji_party_popper() -> String {
-    "🎉".to_string()
-}
-
 pub fn emoji_warning() -> String {
     "⚠️".to_string()
 }
-
-pub fn emoji_counterclockwise_arrows() -> String {
-    "🔄".to_string()
-}
@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+use serenity::all::GuildId;
+use crate::config;
+
+// Built-in copy is English, so this is where every lookup ends up when a key is missing.
+pub const FALLBACK_LOCALE: &str = "en-US";
+
+/// Locale for messages everyone sees, like the welcome embed and officer cards.
+pub fn guild_locale(guild_id: GuildId) -> String {
+    config::Config::for_guild(guild_id).default_locale.unwrap_or_else(|| FALLBACK_LOCALE.to_string())
+}
+
+/// Catalog names to try for a Discord locale, most specific first: "es-419" gives ["es-419", "es"].
+/// The names end up in file paths, so anything that does not look like a locale gives none.
+pub fn candidates(locale: &str) -> Vec<String> {
+    if !is_locale(locale) {
+        return Vec::new();
+    }
+
+    let mut candidates = vec![locale.to_string()];
+    if let Some((language, _)) = locale.split_once('-') {
+        candidates.push(language.to_string());
+    }
+    candidates
+}
+
+/// A language of two or three letters, optionally followed by one region or variant: "de", "pt-BR", "es-419".
+fn is_locale(locale: &str) -> bool {
+    let (language, region) = match locale.split_once('-') {
+        Some((language, region)) => (language, Some(region)),
+        None => (locale, None),
+    };
+
+    (2..=3).contains(&language.len())
+        && language.chars().all(|c| c.is_ascii_alphabetic())
+        && region.is_none_or(|region| !region.is_empty() && region.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+/// Picks the best translation from a map keyed by locale, if there is one.
+pub fn localized<'a>(translations: &'a HashMap<String, String>, locale: &str) -> Option<&'a str> {
+    candidates(locale).iter()
+        .find_map(|candidate| translations.get(candidate))
+        .map(|translation| translation.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn candidates_fall_back_to_the_language() {
+        assert_eq!(candidates("es-419"), vec!["es-419".to_string(), "es".to_string()]);
+        assert_eq!(candidates("pt-BR"), vec!["pt-BR".to_string(), "pt".to_string()]);
+        assert_eq!(candidates("de"), vec!["de".to_string()]);
+    }
+
+    #[test]
+    fn candidates_reject_anything_but_a_locale() {
+        assert!(candidates("../config").is_empty());
+        assert!(candidates("es/../../secrets").is_empty());
+        assert!(candidates("es-").is_empty());
+        assert!(candidates("e").is_empty());
+        assert!(candidates("english").is_empty());
+        assert!(candidates("es-ES-x").is_empty());
+        assert!(candidates("").is_empty());
+    }
+}
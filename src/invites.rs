@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+use serenity::all::{GuildId, UserId};
+use serenity::client::Context;
+use crate::config::Config;
+
+// Use counts of every guild's invites, keyed by guild id and then invite code.
+static INVITE_USES_CACHE: RwLock<Option<HashMap<u64, HashMap<String, u64>>>> = RwLock::new(None);
+
+/// The invite a member joined with.
+#[derive(Debug, Clone)]
+pub struct UsedInvite {
+    pub code: String,
+    pub inviter_id: Option<UserId>,
+}
+
+impl UsedInvite {
+    /// Chapter the invite recruits for, from `invite_chapters` in the guild's config.
+    pub fn chapter_name(guild_id: GuildId, code: &str) -> Option<String> {
+        Config::for_guild(guild_id).invite_chapters.get(code).cloned()
+    }
+}
+
+/// Remembers how often each of the guild's invites has been used, so the next join can be matched to its invite.
+/// Returns the number of invites seen.
+pub async fn cache_invites(ctx: &Context, guild_id: GuildId) -> Result<usize, serenity::Error> {
+    let invites = guild_id.invites(&ctx.http).await?;
+    let uses: HashMap<String, u64> = invites.into_iter().map(|invite| (invite.code, invite.uses)).collect();
+    let count = uses.len();
+
+    if let Ok(mut cache) = INVITE_USES_CACHE.write() {
+        cache.get_or_insert_with(HashMap::new).insert(guild_id.get(), uses);
+    }
+
+    Ok(count)
+}
+
+/// Works out which invite a member who just joined used, by finding the one invite whose use count went up.
+/// Returns None when that is not clear, e.g. when two members joined at once or the invite was single-use
+/// and Discord already deleted it.
+pub async fn detect_used_invite(ctx: &Context, guild_id: GuildId) -> Option<UsedInvite> {
+    let invites = match guild_id.invites(&ctx.http).await {
+        Ok(invites) => invites,
+        Err(e) => {
+            println!("Error fetching invites: {}", e);
+            return None;
+        }
+    };
+
+    let previous_uses = INVITE_USES_CACHE.read().ok()
+        .and_then(|cache| cache.as_ref().and_then(|guilds| guilds.get(&guild_id.get()).cloned()));
+    let uses = invites.iter().map(|invite| (invite.code.clone(), invite.uses)).collect();
+    if let Ok(mut cache) = INVITE_USES_CACHE.write() {
+        cache.get_or_insert_with(HashMap::new).insert(guild_id.get(), uses);
+    }
+
+    // Without counts from before the join there is nothing to compare against.
+    let previous_uses = previous_uses?;
+    let mut used = invites.iter()
+        .filter(|invite| invite.uses > previous_uses.get(&invite.code).copied().unwrap_or(0));
+    match (used.next(), used.next()) {
+        (Some(invite), None) => Some(UsedInvite {
+            code: invite.code.clone(),
+            inviter_id: invite.inviter.as_ref().map(|inviter| inviter.id),
+        }),
+        _ => None,
+    }
+}
@@ -0,0 +1,283 @@
+use serenity::all::{CommandInteraction, CommandType, CreateCommand, CreateEmbed, GuildId, ResolvedValue, UserId};
+use serenity::builder::{CreateInteractionResponse, CreateInteractionResponseMessage};
+use serenity::client::Context;
+use crate::config::Config;
+use crate::member_db::MemberJoinMessage;
+use crate::permissions::{authorize_command, Action};
+use crate::templates::Templates;
+
+// Most matches listed for a search before the rest are left out.
+const MAX_MATCHES: usize = 10;
+// Discord rejects embeds with a longer field value.
+const MAX_FIELD_LENGTH: usize = 1024;
+
+pub async fn register_view_onboarding_command() -> CreateCommand {
+    CreateCommand::new("View onboarding").kind(CommandType::User)
+}
+
+/// `/nmi lookup`: a member's record by mention, or the records whose character, realm or chapter resemble the query.
+pub async fn handle_lookup(ctx: &Context, command: &CommandInteraction) -> Result<(), serenity::Error> {
+    if !authorize_command(ctx, command, Action::Lookup).await? {
+        return Ok(());
+    }
+
+    let guild_id = Config::guild_or_primary(command.guild_id);
+    let templates = Templates::for_locale(&command.locale);
+    let mut user_id = None;
+    let mut query = String::new();
+    for option in command.data.options() {
+        if let ResolvedValue::SubCommand(sub_options) = option.value {
+            for sub_option in sub_options {
+                match sub_option.value {
+                    ResolvedValue::User(user, _) => user_id = Some(user.id),
+                    ResolvedValue::String(value) => query = value.trim().to_string(),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    let response = match user_id {
+        Some(user_id) => record_response(ctx, guild_id, user_id, &templates).await?,
+        None if query.is_empty() => CreateInteractionResponseMessage::new().content(templates.text("lookup_no_query", &[])),
+        None => search_response(ctx, guild_id, &query, &templates).await?,
+    };
+
+    command.create_response(&ctx.http, CreateInteractionResponse::Message(response.ephemeral(true))).await?;
+
+    Ok(())
+}
+
+/// The "View onboarding" user context menu.
+pub async fn handle_view_onboarding(ctx: &Context, command: &CommandInteraction) -> Result<(), serenity::Error> {
+    if !authorize_command(ctx, command, Action::Lookup).await? {
+        return Ok(());
+    }
+
+    let guild_id = Config::guild_or_primary(command.guild_id);
+    let templates = Templates::for_locale(&command.locale);
+    let user_id = command.data.target_id.ok_or(serenity::Error::Other("No target user found"))?.to_user_id();
+
+    let response = record_response(ctx, guild_id, user_id, &templates).await?;
+    command.create_response(&ctx.http, CreateInteractionResponse::Message(response.ephemeral(true))).await?;
+
+    Ok(())
+}
+
+async fn search_response(ctx: &Context, guild_id: GuildId, query: &str, templates: &Templates) -> Result<CreateInteractionResponseMessage, serenity::Error> {
+    let records = MemberJoinMessage::get_all(guild_id.to_string()).await
+        .map_err(|_| serenity::Error::Other("Could not load onboarding records"))?;
+
+    let mut matches: Vec<(usize, MemberJoinMessage)> = MemberJoinMessage::latest_per_member(records).into_iter()
+        .filter_map(|record| {
+            [&record.character_name, &record.realm_name, &record.chapter_name].into_iter()
+                .filter_map(|value| match_score(query, value))
+                .min()
+                .map(|score| (score, record))
+        })
+        .collect();
+    matches.sort_by_key(|(score, _)| *score);
+
+    match matches.len() {
+        0 => Ok(CreateInteractionResponseMessage::new().content(templates.text("lookup_no_matches", &[("query", query.to_string())]))),
+        1 => {
+            let user_id = UserId::new(matches[0].1.discord_user_id);
+            record_response(ctx, guild_id, user_id, templates).await
+        }
+        count => {
+            let lines = matches.iter()
+                .take(MAX_MATCHES)
+                .map(|(_, record)| templates.text("lookup_match", &record_vars(guild_id, record, templates)))
+                .collect::<Vec<String>>()
+                .join("\n");
+            let mut text = templates.text("lookup_matches", &[("query", query.to_string()), ("count", count.to_string()), ("matches", lines)]);
+            if count > MAX_MATCHES {
+                text += "\n";
+                text += &templates.text("lookup_more_matches", &[("count", (count - MAX_MATCHES).to_string())]);
+            }
+            Ok(CreateInteractionResponseMessage::new().content(text))
+        }
+    }
+}
+
+/// The member's latest record with their current roles, how the record got to its stage and a link to the card.
+async fn record_response(ctx: &Context, guild_id: GuildId, user_id: UserId, templates: &Templates) -> Result<CreateInteractionResponseMessage, serenity::Error> {
+    let Ok(record) = MemberJoinMessage::get_message_by_discord_user_id(guild_id.to_string(), user_id.to_string()).await else {
+        return Ok(CreateInteractionResponseMessage::new().content(templates.text("lookup_no_record", &[("member", format!("<@{}>", user_id))])));
+    };
+
+    let vars = record_vars(guild_id, &record, templates);
+    let not_given = || templates.text("me_not_given", &[]);
+    let non_empty = |value: &str| if value.is_empty() { not_given() } else { value.to_string() };
+
+    let roles = match guild_id.member(&ctx.http, user_id).await {
+        Ok(member) if member.roles.is_empty() => templates.text("lookup_no_roles", &[]),
+        Ok(member) => join_field(&member.roles.iter().map(|role_id| format!("<@&{}>", role_id)).collect::<Vec<String>>(), " "),
+        Err(_) => templates.text("lookup_not_in_guild", &[]),
+    };
+
+    let history = match record.get_stage_history().await {
+        Ok(history) if !history.is_empty() => join_field(&history.iter()
+            .map(|(stage, changed_at)| format!("{} UTC: {}", changed_at, templates.text(stage.label_key(), &[])))
+            .collect::<Vec<String>>(), "\n"),
+        Ok(_) => templates.text("lookup_no_history", &[]),
+        Err(e) => {
+            println!("Error getting stage history from database: {}", e);
+            templates.text("lookup_no_history", &[])
+        }
+    };
+
+    let mut embed = CreateEmbed::new()
+        .title(templates.text("lookup_title", &vars))
+        .field("Member", format!("<@{}>", record.discord_user_id), true)
+        .field("Character Name", non_empty(&record.character_name), true)
+        .field("Realm", non_empty(&record.realm_name), true)
+        .field("Chapter", non_empty(&record.chapter_name), true)
+        .field("Status", templates.text(record.stage.label_key(), &[]), true);
+    if let Some(flavour) = record.flavour {
+        embed = embed.field("Game", templates.text(&flavour.label_key(), &[]), true);
+    }
+    if let Some(faction) = record.faction {
+        embed = embed.field("Faction", templates.text(&faction.label_key(), &[]), true);
+    }
+    if let Some(invite_code) = &record.invite_code {
+        let inviter = record.invited_by.map(|inviter_id| format!("<@{}>", inviter_id)).unwrap_or_else(|| templates.text("inviter_unknown", &[]));
+        embed = embed.field("Invited via", templates.text("invited_via", &[("code", invite_code.clone()), ("inviter", inviter)]), true);
+    }
+    if let Some(requested_chapter) = &record.requested_chapter {
+        embed = embed.field("Requested Chapter", requested_chapter.clone(), true);
+    }
+    let embed = embed
+        .field("Roles", roles, false)
+        .field("History", history, false)
+        .field("Card", card_link(guild_id, &record), false);
+
+    Ok(CreateInteractionResponseMessage::new().embed(embed))
+}
+
+/// Joins as many of the last items as fit in one embed field, so the latest history is what is kept.
+fn join_field(items: &[String], separator: &str) -> String {
+    let mut length = "…".len() + separator.len();
+    let mut kept = 0;
+    for item in items.iter().rev() {
+        length += item.len() + separator.len();
+        if length > MAX_FIELD_LENGTH {
+            break;
+        }
+        kept += 1;
+    }
+
+    let joined = items[items.len() - kept..].join(separator);
+    if kept == items.len() { joined } else { format!("…{}{}", separator, joined) }
+}
+
+fn record_vars(guild_id: GuildId, record: &MemberJoinMessage, templates: &Templates) -> [(&'static str, String); 6] {
+    [
+        ("member", format!("<@{}>", record.discord_user_id)),
+        ("character", record.character_name.clone()),
+        ("realm", record.realm_name.clone()),
+        ("chapter", record.chapter_name.clone()),
+        ("stage", templates.text(record.stage.label_key(), &[])),
+        ("card", card_link(guild_id, record)),
+    ]
+}
+
+fn card_link(guild_id: GuildId, record: &MemberJoinMessage) -> String {
+    format!("https://discord.com/channels/{}/{}/{}", guild_id, Config::for_guild(guild_id).nmi_channel_id, record.message_id)
+}
+
+/// How well `value` matches what an officer typed: 0 for the same name, 1 when one contains the other,
+/// and 2 plus the number of typos otherwise. None when they are too far apart. Case, spaces and
+/// punctuation are ignored, so "area 52" finds Area52 and "kel'thuzad" finds KelThuzad.
+fn match_score(query: &str, value: &str) -> Option<usize> {
+    let normalize = |text: &str| text.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect::<Vec<char>>();
+    let query = normalize(query);
+    let value = normalize(value);
+    if query.is_empty() || value.is_empty() {
+        return None;
+    }
+
+    if query == value {
+        return Some(0);
+    }
+    if contains(&value, &query) || (contains(&query, &value) && value.len() >= 3) {
+        return Some(1);
+    }
+
+    // Allow about one typo per four letters.
+    let allowed = (query.len() / 4).max(1);
+    let distance = edit_distance(&query, &value);
+    (distance <= allowed).then_some(2 + distance)
+}
+
+fn contains(haystack: &[char], needle: &[char]) -> bool {
+    needle.len() <= haystack.len() && haystack.windows(needle.len()).any(|window| window == needle)
+}
+
+/// Levenshtein distance: the fewest single letter insertions, deletions and substitutions turning one into the other.
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(text: &str) -> Vec<char> {
+        text.chars().collect()
+    }
+
+    #[test]
+    fn edit_distance_counts_single_letter_edits() {
+        assert_eq!(edit_distance(&chars("stormrage"), &chars("stormrage")), 0);
+        assert_eq!(edit_distance(&chars("stormrage"), &chars("stromrage")), 2);
+        assert_eq!(edit_distance(&chars("kitten"), &chars("sitting")), 3);
+        assert_eq!(edit_distance(&chars(""), &chars("area")), 4);
+        assert_eq!(edit_distance(&chars("area"), &chars("")), 4);
+    }
+
+    #[test]
+    fn match_score_ignores_case_and_punctuation() {
+        assert_eq!(match_score("area 52", "Area52"), Some(0));
+        assert_eq!(match_score("kel'thuzad", "KelThuzad"), Some(0));
+    }
+
+    #[test]
+    fn match_score_ranks_contained_names_before_typos() {
+        assert_eq!(match_score("storm", "Stormrage"), Some(1));
+        assert_eq!(match_score("Stormrage EU", "Stormrage"), Some(1));
+        assert_eq!(match_score("stormrgae", "Stormrage"), Some(4));
+    }
+
+    #[test]
+    fn match_score_rejects_distant_and_empty_values() {
+        assert_eq!(match_score("tichondrius", "Area 52"), None);
+        assert_eq!(match_score("", "Area 52"), None);
+        assert_eq!(match_score("area", "'"), None);
+        // Short values inside a long query are too likely to be chance.
+        assert_eq!(match_score("illidan", "il"), None);
+    }
+
+    #[test]
+    fn join_field_keeps_the_last_items_within_the_limit() {
+        let items = (0..200).map(|index| format!("entry {:03}", index)).collect::<Vec<String>>();
+        let joined = join_field(&items, "\n");
+        assert!(joined.len() <= MAX_FIELD_LENGTH);
+        assert!(joined.starts_with("…\n"));
+        assert!(joined.ends_with("entry 199"));
+        assert!(!joined.contains("entry 000"));
+
+        let few = vec!["a".to_string(), "b".to_string()];
+        assert_eq!(join_field(&few, " "), "a b");
+    }
+}
@@ -2,11 +2,28 @@ mod nmi_handler;
 mod chapters;
 mod message_command;
 mod secrets;
+mod config;
 mod emojis;
 mod member_info;
 mod member_db;
+mod nickname;
+mod i18n;
+mod questionnaire;
+mod rules;
+mod templates;
+mod permissions;
+mod nmi_command;
+mod chapter_command;
+mod self_check;
+mod config_command;
+mod waitlist;
+mod invites;
+mod me_command;
+mod lookup;
+mod context_menu;
 
-use serenity::all::{Interaction, Member};
+use std::sync::atomic::{AtomicBool, Ordering};
+use serenity::all::{Interaction, Member, User};
 use serenity::async_trait;
 use serenity::model::channel::Message;
 use serenity::model::gateway::Ready;
@@ -17,24 +34,80 @@ use serde_json;
 use serde::{Deserialize, Serialize};
 use crate::message_command::send_welcome_message;
 use crate::chapters::{Chapter, Chapters};
-use crate::member_info::{handle_complete_onboarding, handle_member_join, handle_undo_completion};
+use crate::member_info::{handle_change_chapter, handle_change_chapter_response, handle_change_character, handle_change_character_response, handle_chapter_request, handle_complete_onboarding, handle_member_join, handle_member_leave, handle_reject_registration, handle_undo_completion};
 
 struct Handler;
 
+static FIRST_READY_SEEN: AtomicBool = AtomicBool::new(false);
+
 #[async_trait]
 impl EventHandler for Handler {
     async fn ready(&self, ctx: Context, _ready: Ready) {
         println!("The bot is connected!");
 
-        let secrets = secrets::Secrets::get_secrets();
+        // Discord sends Ready again after a reconnect; the configuration and cards are only checked at startup.
+        let first_ready = !FIRST_READY_SEEN.swap(true, Ordering::SeqCst);
+
+        for guild_id in config::Config::guild_ids() {
+            if first_ready {
+                match self_check::run(&ctx, guild_id).await {
+                    Ok(report) => {
+                        println!("Guild {}:\n{}", guild_id, report);
+                        if let Err(e) = self_check::post_report(&ctx, guild_id, &report).await {
+                            println!("Error posting configuration report: {}", e);
+                        }
+                        if report.has_fatal() {
+                            eprintln!("Fatal configuration problems found in guild {}, see the report above. Fix config.json or {} and restart.", guild_id, config::Config::chapters_path(guild_id));
+                            std::process::exit(1);
+                        }
+                    }
+                    // A Discord outage is not a configuration problem, so the bot keeps running.
+                    Err(e) => {
+                        eprintln!("Could not check the configuration against guild {}: {}. Is guild_id right and is the bot in the guild?", guild_id, e);
+                    }
+                }
+            }
+
+            match invites::cache_invites(&ctx, guild_id).await {
+                Ok(count) => println!("Tracking {} invite(s) in guild {}.", count, guild_id),
+                Err(e) => println!("Error caching invites of guild {}: {}", guild_id, e),
+            }
+
+            // Cards deleted while the bot was offline would otherwise leave their members stuck.
+            if first_ready {
+                match member_info::reconcile_cards(&ctx, guild_id).await {
+                    Ok(0) => {}
+                    Ok(count) => println!("Re-posted {} missing card(s) in guild {}.", count, guild_id),
+                    Err(e) => println!("Error checking cards of guild {}: {}", guild_id, e),
+                }
+            }
 
-        let guild_id = GuildId::new(secrets.guild_id);
+            let command = message_command::register_welcome_message_command().await;
+            let rules_command = rules::register_rules_command().await;
+            let welcome_command = message_command::register_welcome_command().await;
+            let template_command = templates::register_template_command().await;
+            let nmi_command = nmi_command::register_nmi_command().await;
+            let chapter_command = chapter_command::register_chapter_command().await;
+            let config_command = config_command::register_config_command().await;
+            let me_command = me_command::register_me_command().await;
+            let view_onboarding_command = lookup::register_view_onboarding_command().await;
+            let mut commands = vec![command, rules_command, welcome_command, template_command, nmi_command, chapter_command, config_command, me_command, view_onboarding_command];
+            commands.extend(context_menu::register_context_menu_commands().await);
+            guild_id.set_commands(&ctx.http, commands).await.expect("Could not register commands.");
+        }
 
-        let command = message_command::register_welcome_message_command().await;
-        guild_id.set_commands(&ctx.http, vec![command]).await.expect("Could not register commands.");
+        // chapters.json may have changed while the bot was offline.
+        match message_command::refresh_welcome_messages(&ctx).await {
+            Ok(updated) => println!("Refreshed {} welcome message(s).", updated),
+            Err(e) => println!("Error refreshing welcome messages: {}", e),
+        }
     }
     
     async fn guild_member_addition(&self, ctx: Context, new_member: Member) {
+        if !config::Config::is_configured(new_member.guild_id) {
+            return;
+        }
+
         let result = handle_member_join(&ctx, &new_member).await;
         match result {
             Ok(_) => {
@@ -46,7 +119,26 @@ impl EventHandler for Handler {
         }
     }
 
+    async fn guild_member_removal(&self, ctx: Context, guild_id: GuildId, user: User, _member: Option<Member>) {
+        if !config::Config::is_configured(guild_id) {
+            return;
+        }
+
+        handle_member_leave(&ctx, guild_id, &user).await;
+    }
+
     async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        // The bot may sit in guilds it has no settings for. Interactions outside a guild are let through.
+        let guild_id = match &interaction {
+            Interaction::Command(command) => command.guild_id,
+            Interaction::Component(component) => component.guild_id,
+            Interaction::Modal(modal) => modal.guild_id,
+            _ => None,
+        };
+        if guild_id.is_some_and(|guild_id| !config::Config::is_configured(guild_id)) {
+            return;
+        }
+
         if let Interaction::Component(component) = interaction.clone() {
             if component.data.custom_id == "nmi_button" {
                 let response = nmi_handler::nmi_modal(&ctx, &component).await;
@@ -61,6 +153,75 @@ impl EventHandler for Handler {
                 }
             }
 
+            if component.data.custom_id.starts_with("nmi_flavour:") {
+                let response = nmi_handler::nmi_flavour(&ctx, &component).await;
+
+                match response {
+                    Ok(_) => {
+
+                    }
+                    Err(e) => {
+                        println!("Error handling NMI flavour button: {}", e);
+                    }
+                }
+            }
+
+            if component.data.custom_id.starts_with("nmi_faction:") {
+                let response = nmi_handler::nmi_faction(&ctx, &component).await;
+
+                match response {
+                    Ok(_) => {
+
+                    }
+                    Err(e) => {
+                        println!("Error handling NMI faction button: {}", e);
+                    }
+                }
+            }
+
+            if component.data.custom_id.starts_with("nmi_continue:") {
+                let response = nmi_handler::nmi_continue(&ctx, &component).await;
+
+                match response {
+                    Ok(_) => {
+
+                    }
+                    Err(e) => {
+                        println!("Error handling NMI continue button: {}", e);
+                    }
+                }
+            }
+
+            if component.data.custom_id.starts_with("rules_accept") {
+                let response = rules::handle_rules_accept(&ctx, &component).await;
+
+                match response {
+                    Ok(true) => {
+                        if let Err(e) = nmi_handler::nmi_modal(&ctx, &component).await {
+                            println!("Error handling NMI button: {}", e);
+                        }
+                    }
+                    Ok(false) => {
+
+                    }
+                    Err(e) => {
+                        println!("Error handling rules acceptance: {}", e);
+                    }
+                }
+            }
+
+            if component.data.custom_id.starts_with("chapter_import_") {
+                let response = chapter_command::handle_import_button(&ctx, &component).await;
+                match response {
+                    Ok(_) => {
+
+                    }
+                    Err(e) => {
+                        println!("Error handling chapter import button: {}", e);
+                    }
+                }
+            }
+
             if component.data.custom_id == "guest_button" {
                 // TODO
             }
@@ -78,7 +239,7 @@ impl EventHandler for Handler {
             }
 
             if component.data.custom_id == "button_undo_completed" {
-                let result = handle_undo_completion(&ctx, component).await;
+                let result = handle_undo_completion(&ctx, component.clone()).await;
                 match result {
                     Ok(_) => {
 
@@ -88,10 +249,58 @@ impl EventHandler for Handler {
                     }
                 }
             }
+
+            if component.data.custom_id == "button_reject_registration" {
+                let result = handle_reject_registration(&ctx, component.clone()).await;
+                match result {
+                    Ok(_) => {
+
+                    }
+                    Err(e) => {
+                        println!("Error handling reject onboarding: {}", e);
+                    }
+                }
+            }
+
+            if component.data.custom_id == "button_approve_chapter_request" || component.data.custom_id == "button_deny_chapter_request" {
+                let result = handle_chapter_request(&ctx, component.clone()).await;
+                match result {
+                    Ok(_) => {
+
+                    }
+                    Err(e) => {
+                        println!("Error handling chapter request: {}", e);
+                    }
+                }
+            }
+
+            if component.data.custom_id == "button_change_chapter" {
+                let result = handle_change_chapter(&ctx, component.clone()).await;
+                match result {
+                    Ok(_) => {
+
+                    }
+                    Err(e) => {
+                        println!("Error handling change chapter: {}", e);
+                    }
+                }
+            }
+
+            if component.data.custom_id == "button_change_character" {
+                let result = handle_change_character(&ctx, component).await;
+                match result {
+                    Ok(_) => {
+
+                    }
+                    Err(e) => {
+                        println!("Error handling change character: {}", e);
+                    }
+                }
+            }
         }
 
         if let Interaction::Modal(modal) = interaction.clone() {
-            if modal.data.custom_id == "nmi_modal" {
+            if modal.data.custom_id.starts_with("nmi_modal:") {
                 let response = nmi_handler::nmi_modal_response(&ctx, &modal).await;
                 match response {
                     Ok(_) => {
@@ -102,11 +311,187 @@ impl EventHandler for Handler {
                     }
                 }
             }
+
+            if modal.data.custom_id == "change_chapter_modal" {
+                let response = handle_change_chapter_response(&ctx, &modal).await;
+                match response {
+                    Ok(_) => {
+
+                    }
+                    Err(e) => {
+                        println!("Error handling change chapter modal: {}", e);
+                    }
+                }
+            }
+
+            if modal.data.custom_id == "change_character_modal" {
+                let response = handle_change_character_response(&ctx, &modal).await;
+                match response {
+                    Ok(_) => {
+
+                    }
+                    Err(e) => {
+                        println!("Error handling change character modal: {}", e);
+                    }
+                }
+            }
         }
 
         if let Interaction::Command(command) = interaction.clone() {
             if command.data.name.as_str() == "create_welcome_message" {
-                send_welcome_message(ctx, command).await;
+                let response = send_welcome_message(ctx.clone(), command.clone()).await;
+                match response {
+                    Ok(_) => {
+
+                    }
+                    Err(e) => {
+                        println!("Error handling create welcome message: {}", e);
+                    }
+                }
+            }
+
+            if command.data.name.as_str() == "welcome" {
+                let response = message_command::handle_welcome_command(&ctx, &command).await;
+                match response {
+                    Ok(_) => {
+
+                    }
+                    Err(e) => {
+                        println!("Error handling welcome command: {}", e);
+                    }
+                }
+            }
+
+            if command.data.name.as_str() == "template" {
+                let response = templates::handle_template_command(&ctx, &command).await;
+                match response {
+                    Ok(_) => {
+
+                    }
+                    Err(e) => {
+                        println!("Error handling template command: {}", e);
+                    }
+                }
+            }
+
+            if command.data.name.as_str() == "rules" {
+                let response = rules::handle_rules_command(&ctx, &command).await;
+                match response {
+                    Ok(_) => {
+
+                    }
+                    Err(e) => {
+                        println!("Error handling rules command: {}", e);
+                    }
+                }
+            }
+
+            if command.data.name.as_str() == "nmi" {
+                let response = nmi_command::handle_nmi_command(&ctx, &command).await;
+                match response {
+                    Ok(_) => {
+
+                    }
+                    Err(e) => {
+                        println!("Error handling nmi command: {}", e);
+                    }
+                }
+            }
+
+            if command.data.name.as_str() == "chapter" {
+                let response = chapter_command::handle_chapter_command(&ctx, &command).await;
+                match response {
+                    Ok(_) => {
+
+                    }
+                    Err(e) => {
+                        println!("Error handling chapter command: {}", e);
+                    }
+                }
+            }
+
+            if command.data.name.as_str() == "config" {
+                let response = config_command::handle_config_command(&ctx, &command).await;
+                match response {
+                    Ok(_) => {
+
+                    }
+                    Err(e) => {
+                        println!("Error handling config command: {}", e);
+                    }
+                }
+            }
+
+            if command.data.name.as_str() == "View onboarding" {
+                let response = lookup::handle_view_onboarding(&ctx, &command).await;
+                match response {
+                    Ok(_) => {
+
+                    }
+                    Err(e) => {
+                        println!("Error handling view onboarding: {}", e);
+                    }
+                }
+            }
+
+            if command.data.name.as_str() == "Re-send onboarding form" {
+                let response = context_menu::handle_resend_form(&ctx, &command).await;
+                match response {
+                    Ok(_) => {
+
+                    }
+                    Err(e) => {
+                        println!("Error handling re-send onboarding form: {}", e);
+                    }
+                }
+            }
+
+            if command.data.name.as_str() == "Reset onboarding" {
+                let response = context_menu::handle_reset_onboarding(&ctx, &command).await;
+                match response {
+                    Ok(_) => {
+
+                    }
+                    Err(e) => {
+                        println!("Error handling reset onboarding: {}", e);
+                    }
+                }
+            }
+
+            if command.data.name.as_str() == "Mark complete" {
+                let response = context_menu::handle_mark_complete(&ctx, &command).await;
+                match response {
+                    Ok(_) => {
+
+                    }
+                    Err(e) => {
+                        println!("Error handling mark complete: {}", e);
+                    }
+                }
+            }
+
+            if command.data.name.as_str() == "Rebuild card from database" {
+                let response = context_menu::handle_rebuild_card(&ctx, &command).await;
+                match response {
+                    Ok(_) => {
+
+                    }
+                    Err(e) => {
+                        println!("Error handling rebuild card: {}", e);
+                    }
+                }
+            }
+
+            if command.data.name.as_str() == "me" {
+                let response = me_command::handle_me_command(&ctx, &command).await;
+                match response {
+                    Ok(_) => {
+
+                    }
+                    Err(e) => {
+                        println!("Error handling me command: {}", e);
+                    }
+                }
             }
         }
     }
@@ -114,11 +499,13 @@ impl EventHandler for Handler {
 
 #[tokio::main]
 async fn main() {
+    // Fail before connecting if the config file is missing or invalid.
+    let config = config::Config::load();
+
     // Load chapters from JSON
-    let chapters = Chapters::load();
+    let chapters = Chapters::for_guild(GuildId::new(config.guild_id));
     println!("{}", chapters.to_formatted_list());
     println!("\n\n Example: {}-{}", chapters.get_by_id(1).unwrap().name, chapters.get_by_id(1).unwrap().get_role_id().to_string());
-
     let secrets = secrets::Secrets::get_secrets();
 
     // Only intents needed for interactions, may be none.
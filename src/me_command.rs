@@ -0,0 +1,162 @@
+use serenity::all::{CommandInteraction, CommandOptionType, CreateCommand, CreateCommandOption, GuildId};
+use serenity::builder::{CreateInteractionResponse, CreateInteractionResponseMessage};
+use serenity::client::Context;
+use crate::chapter_command::string_options;
+use crate::chapters::Chapters;
+use crate::member_db::{MemberJoinMessage, MemberJoinMessageStage};
+use crate::member_info::{flag_chapter_request, reflag_edited_card};
+use crate::nickname::apply_nickname_policy;
+use crate::questionnaire::{Questionnaire, FIELD_CHARACTER_NAME, FIELD_REALM_NAME};
+use crate::templates::Templates;
+use crate::waitlist;
+
+pub async fn register_me_command() -> CreateCommand {
+    let status = CreateCommandOption::new(CommandOptionType::SubCommand, "status", "Show your registration.");
+
+    let edit = CreateCommandOption::new(CommandOptionType::SubCommand, "edit", "Fix your character or realm. Officers review the change.")
+        .add_sub_option(CreateCommandOption::new(CommandOptionType::String, "character", "Your character's name.").required(false))
+        .add_sub_option(CreateCommandOption::new(CommandOptionType::String, "realm", "Your character's realm.").required(false));
+
+    let chapter = CreateCommandOption::new(CommandOptionType::SubCommand, "chapter", "Ask to move to another chapter. Officers approve the move.")
+        .add_sub_option(CreateCommandOption::new(CommandOptionType::String, "chapter", "Number or name of the chapter.").required(true));
+
+    CreateCommand::new("me").description("Your chapter registration.")
+        .add_option(status)
+        .add_option(edit)
+        .add_option(chapter)
+}
+
+pub async fn handle_me_command(ctx: &Context, command: &CommandInteraction) -> Result<(), serenity::Error> {
+    let subcommand = command.data.options().first().map(|option| option.name).unwrap_or_default();
+    let guild_id = command.guild_id.ok_or(serenity::Error::Other("No guild ID found"))?;
+    let templates = Templates::for_locale(&command.locale);
+
+    // Every subcommand works on the member's own record, which only exists once they joined through the bot.
+    let reply = match MemberJoinMessage::get_message_by_discord_user_id(guild_id.to_string(), command.user.id.to_string()).await {
+        Ok(record) => match subcommand {
+            "status" => status(guild_id, &record, &templates),
+            "edit" => edit(ctx, command, guild_id, &record, &templates).await?,
+            "chapter" => request_chapter(ctx, command, guild_id, &record, &templates).await?,
+            _ => return Ok(()),
+        },
+        Err(_) => templates.text("me_no_record", &[]),
+    };
+
+    command.create_response(
+        &ctx.http,
+        CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::default()
+                .ephemeral(true)
+                .content(reply)
+        ),
+    ).await?;
+
+    Ok(())
+}
+
+fn status(guild_id: GuildId, record: &MemberJoinMessage, templates: &Templates) -> String {
+    let not_given = || templates.text("me_not_given", &[]);
+    let non_empty = |value: &str| if value.is_empty() { not_given() } else { value.to_string() };
+    let mut chapter = non_empty(&record.chapter_name);
+    if let Some(flavour) = Chapters::for_guild(guild_id).get_by_name(&record.chapter_name).map(|chapter| chapter.flavour) {
+        chapter = format!("{} ({})", chapter, templates.text(&flavour.label_key(), &[]));
+    }
+
+    let mut text = templates.text("me_status", &[
+        ("stage", templates.text(record.stage.label_key(), &[])),
+        ("character", non_empty(&record.character_name)),
+        ("realm", non_empty(&record.realm_name)),
+        ("chapter", chapter),
+    ]);
+    if let Some(requested_chapter) = &record.requested_chapter {
+        text += "\n";
+        text += &templates.text("me_status_chapter_request", &[("chapter", requested_chapter.clone())]);
+    }
+
+    text
+}
+
+/// Only members an officer is looking at, or has approved, have a card worth changing.
+fn has_card(record: &MemberJoinMessage) -> bool {
+    matches!(record.stage, MemberJoinMessageStage::Onboarding | MemberJoinMessageStage::Completed)
+}
+
+async fn edit(ctx: &Context, command: &CommandInteraction, guild_id: GuildId, record: &MemberJoinMessage, templates: &Templates) -> Result<String, serenity::Error> {
+    if !has_card(record) {
+        return Ok(templates.text("me_not_onboarded", &[]));
+    }
+
+    let mut options = string_options(command);
+    let character_name = options.remove("character");
+    let realm_name = options.remove("realm");
+    if character_name.is_none() && realm_name.is_none() {
+        return Ok(templates.text("me_edit_nothing", &[]));
+    }
+
+    let questionnaire = Questionnaire::load();
+    for (field_id, value) in [(FIELD_CHARACTER_NAME, &character_name), (FIELD_REALM_NAME, &realm_name)] {
+        if let (Some(field), Some(value)) = (questionnaire.get_field(field_id), value)
+            && let Err(reason) = field.validate(guild_id, None, value, &command.locale) {
+            return Ok(reason);
+        }
+    }
+
+    let character_name = character_name.unwrap_or_else(|| record.character_name.clone());
+    let realm_name = realm_name.unwrap_or_else(|| record.realm_name.clone());
+    let chapters = Chapters::for_guild(guild_id);
+    let flavour = record.flavour
+        .or_else(|| chapters.get_by_name(&record.chapter_name).map(|chapter| chapter.flavour))
+        .unwrap_or_default();
+    let result = record.update_details(character_name.clone(), realm_name.clone(), record.chapter_name.clone(), flavour, record.faction).await;
+    if let Err(e) = result {
+        println!("Error storing edited details in database: {}", e);
+        return Err(serenity::Error::Other("Could not store edited details"));
+    }
+    // An approved member goes back to awaiting approval until an officer looks at the change.
+    if let Err(e) = record.update_message(MemberJoinMessageStage::Onboarding).await {
+        println!("Error updating message in database: {}", e);
+    }
+    println!("{} edited their registration to {} - {}", command.user.id, character_name, realm_name);
+
+    let member = guild_id.member(&ctx.http, command.user.id).await?;
+    apply_nickname_policy(ctx, &member, &character_name, &realm_name, &record.chapter_name).await;
+
+    if let Err(e) = reflag_edited_card(ctx, guild_id, record, &character_name, &realm_name).await {
+        println!("Error flagging edited card: {}", e);
+    }
+
+    Ok(templates.text("me_edited", &[("character", character_name), ("realm", realm_name)]))
+}
+
+async fn request_chapter(ctx: &Context, command: &CommandInteraction, guild_id: GuildId, record: &MemberJoinMessage, templates: &Templates) -> Result<String, serenity::Error> {
+    if !has_card(record) {
+        return Ok(templates.text("me_not_onboarded", &[]));
+    }
+
+    let wanted = string_options(command).remove("chapter").unwrap_or_default();
+    let chapters = Chapters::for_guild(guild_id);
+    // Members usually know the number from the welcome list, but a name works too.
+    let chapter_id = wanted.parse::<usize>().ok().or_else(|| chapters.find_id_by_name(&wanted));
+    let Some(chapter) = chapter_id.and_then(|id| chapters.get_active_by_id(id)) else {
+        return Ok(templates.text("invalid_chapter", &[]));
+    };
+    if chapter.name == record.chapter_name {
+        return Ok(templates.text("me_chapter_same", &[("chapter", chapter.name.clone())]));
+    }
+    // Officers could not approve the move anyway.
+    if waitlist::free_slots(guild_id, chapter).await == 0 {
+        return Ok(templates.text("me_chapter_full", &[("chapter", chapter.name.clone())]));
+    }
+
+    if let Err(e) = record.set_requested_chapter(chapter.name.clone()).await {
+        println!("Error storing chapter request in database: {}", e);
+        return Err(serenity::Error::Other("Could not store chapter request"));
+    }
+    println!("{} asked to move from {} to {}", command.user.id, record.chapter_name, chapter.name);
+
+    if let Err(e) = flag_chapter_request(ctx, guild_id, record, chapter).await {
+        println!("Error flagging chapter request on card: {}", e);
+    }
+
+    Ok(templates.text("me_chapter_requested", &[("chapter", chapter.name.clone())]))
+}
@@ -1,5 +1,7 @@
 use std::sync::RwLock;
-use turso::{Builder, Connection, Error, Rows};
+use crate::chapters::{Faction, Flavour};
+use crate::config::Config;
+use turso::{Builder, Connection, Error, Row, Rows};
 
 static SQLITE_CONN: RwLock<Option<Connection>> = RwLock::new(None);
 
@@ -8,6 +10,8 @@ pub enum MemberJoinMessageStage {
     NewMember = 0,
     Onboarding = 1,
     Completed = 2,
+    Rejected = 3,
+    Left = 4,
 }
 
 impl From<i64> for MemberJoinMessageStage {
@@ -16,17 +20,34 @@ impl From<i64> for MemberJoinMessageStage {
             0 => MemberJoinMessageStage::NewMember,
             1 => MemberJoinMessageStage::Onboarding,
             2 => MemberJoinMessageStage::Completed,
+            3 => MemberJoinMessageStage::Rejected,
+            4 => MemberJoinMessageStage::Left,
             _ => MemberJoinMessageStage::NewMember,
         }
     }
 }
 
+impl MemberJoinMessageStage {
+    /// Template string describing the stage, as shown in a card's Status field.
+    pub fn label_key(&self) -> &'static str {
+        match self {
+            MemberJoinMessageStage::NewMember => "status_awaiting_onboarding",
+            MemberJoinMessageStage::Onboarding => "status_awaiting_approval",
+            MemberJoinMessageStage::Completed => "status_complete",
+            MemberJoinMessageStage::Rejected => "status_rejected",
+            MemberJoinMessageStage::Left => "status_left",
+        }
+    }
+}
+
 impl ToString for MemberJoinMessageStage {
     fn to_string(&self) -> String {
         match self {
             MemberJoinMessageStage::NewMember => "0".to_string(),
             MemberJoinMessageStage::Onboarding => "1".to_string(),
             MemberJoinMessageStage::Completed => "2".to_string(),
+            MemberJoinMessageStage::Rejected => "3".to_string(),
+            MemberJoinMessageStage::Left => "4".to_string(),
             _ => "0".to_string(),
         }
     }
@@ -38,10 +59,25 @@ pub struct MemberJoinMessage {
     pub discord_user_id: u64,
     pub message_id: u64,
     pub stage: MemberJoinMessageStage,
+    pub character_name: String,
+    pub realm_name: String,
+    pub chapter_name: String,
+    /// Game flavour of the submission, which is also the flavour of `realm_name`. The realm is free text,
+    /// so it is not checked itself; the chapter is, against the flavour the member picked, and its flavour is stored here.
+    /// None for records from before flavours existed.
+    pub flavour: Option<Flavour>,
+    /// Faction picked for a split chapter. None when the chapter is not split.
+    pub faction: Option<Faction>,
+    /// Invite code the member joined with, when it could be told apart.
+    pub invite_code: Option<String>,
+    /// Creator of that invite.
+    pub invited_by: Option<u64>,
+    /// Chapter the member asked to move to with `/me chapter`, until an officer decides.
+    pub requested_chapter: Option<String>,
 }
 
 impl MemberJoinMessage {
-    async fn get_connection() -> Result<Connection, Error> {
+    pub(crate) async fn get_connection() -> Result<Connection, Error> {
         if let Ok(cache) = SQLITE_CONN.read() {
             if let Some(db) = cache.as_ref() {
                 return Ok(db.clone());
@@ -56,23 +92,101 @@ impl MemberJoinMessage {
 
         // u64 values need to be stored as TEXT. Internally, INTEGER is i64.
         conn.execute(
-            "CREATE TABLE IF NOT EXIST member_join_messages (\
+            "CREATE TABLE IF NOT EXISTS member_join_messages (\
             id INTEGER PRIMARY KEY,\
             discord_user_id TEXT,\
             message_id TEXT,\
             stage INTEGER)", ()
         ).await?;
 
+        // Columns added after the initial schema. ADD COLUMN fails when the column already exists,
+        // which is expected on every start after the first.
+        let added_columns = [
+            "ALTER TABLE member_join_messages ADD COLUMN character_name TEXT NOT NULL DEFAULT ''",
+            "ALTER TABLE member_join_messages ADD COLUMN realm_name TEXT NOT NULL DEFAULT ''",
+            "ALTER TABLE member_join_messages ADD COLUMN chapter_name TEXT NOT NULL DEFAULT ''",
+            "ALTER TABLE member_join_messages ADD COLUMN flavour TEXT NOT NULL DEFAULT ''",
+            "ALTER TABLE member_join_messages ADD COLUMN faction TEXT NOT NULL DEFAULT ''",
+            "ALTER TABLE member_join_messages ADD COLUMN invite_code TEXT NOT NULL DEFAULT ''",
+            "ALTER TABLE member_join_messages ADD COLUMN invited_by TEXT NOT NULL DEFAULT ''",
+            "ALTER TABLE member_join_messages ADD COLUMN requested_chapter TEXT NOT NULL DEFAULT ''",
+        ];
+        for statement in added_columns {
+            let _ = conn.execute(statement, ()).await;
+        }
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS member_answers (\
+            id INTEGER PRIMARY KEY,\
+            discord_user_id TEXT,\
+            field_id TEXT,\
+            value TEXT)", ()
+        ).await?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS rules_acceptances (\
+            id INTEGER PRIMARY KEY,\
+            discord_user_id TEXT,\
+            version INTEGER,\
+            accepted_at TEXT)", ()
+        ).await?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS welcome_messages (\
+            id INTEGER PRIMARY KEY,\
+            channel_id TEXT,\
+            message_id TEXT)", ()
+        ).await?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS config_changes (\
+            id INTEGER PRIMARY KEY,\
+            discord_user_id TEXT,\
+            setting TEXT,\
+            old_value TEXT,\
+            new_value TEXT,\
+            changed_at TEXT)", ()
+        ).await?;
+
+        // Every stage a record passed through. Records from before this table have no history.
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS stage_changes (\
+            id INTEGER PRIMARY KEY,\
+            record_id INTEGER,\
+            stage INTEGER,\
+            changed_at TEXT)", ()
+        ).await?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS chapter_waitlist (\
+            id INTEGER PRIMARY KEY,\
+            guild_id TEXT,\
+            discord_user_id TEXT,\
+            chapter_name TEXT,\
+            locale TEXT,\
+            added_at TEXT)", ()
+        ).await?;
+
+        // Rows are kept per guild. Rows from before multi-guild support belong to the primary guild.
+        let guild_tables = ["member_join_messages", "member_answers", "rules_acceptances", "welcome_messages", "config_changes"];
+        let primary_guild_id = Config::load().guild_id.to_string();
+        for table in guild_tables {
+            let _ = conn.execute(&format!("ALTER TABLE {} ADD COLUMN guild_id TEXT NOT NULL DEFAULT ''", table), ()).await;
+            conn.execute(&format!("UPDATE {} SET guild_id = ?1 WHERE guild_id = ''", table), [primary_guild_id.clone()]).await?;
+        }
+
         Ok(conn)
     }
 
-    pub async fn push_message(discord_user_id: String, message_id: String, stage: MemberJoinMessageStage) -> Result<(), Error> {
+    /// Stores a new join. The invite code and inviter are empty when the invite used is not known.
+    pub async fn push_message(guild_id: String, discord_user_id: String, message_id: String, stage: MemberJoinMessageStage, invite_code: String, invited_by: String) -> Result<(), Error> {
         let conn = Self::get_connection().await?;
         let in_stage = stage as i32;
         conn.execute(
-            "INSERT INTO member_join_messages (discord_user_id, message_id, stage) VALUES (?1, ?2, ?3)",
-            [discord_user_id, message_id, in_stage.to_string()]
+            "INSERT INTO member_join_messages (guild_id, discord_user_id, message_id, stage, invite_code, invited_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            [guild_id, discord_user_id, message_id, in_stage.to_string(), invite_code, invited_by]
         ).await?;
+        Self::record_stage_change(&conn, conn.last_insert_rowid(), in_stage).await?;
 
         Ok(())
     }
@@ -84,15 +198,163 @@ impl MemberJoinMessage {
             "UPDATE member_join_messages SET stage = ?1 WHERE id = ?2",
             [in_stage.to_string(), self.id.to_string()]
         ).await?;
+        Self::record_stage_change(&conn, self.id, in_stage).await?;
+
+        Ok(())
+    }
+
+    /// Points the record at a re-posted card.
+    pub async fn set_message_id(&self, message_id: String) -> Result<(), Error> {
+        let conn = Self::get_connection().await?;
+        conn.execute(
+            "UPDATE member_join_messages SET message_id = ?1 WHERE id = ?2",
+            [message_id, self.id.to_string()]
+        ).await?;
+
+        Ok(())
+    }
+
+    async fn record_stage_change(conn: &Connection, record_id: i64, stage: i32) -> Result<(), Error> {
+        conn.execute(
+            "INSERT INTO stage_changes (record_id, stage, changed_at) VALUES (?1, ?2, CURRENT_TIMESTAMP)",
+            (record_id, stage)
+        ).await?;
 
         Ok(())
     }
 
-    pub async fn get_message_by_discord_user_id(discord_user_id: String) -> Result<MemberJoinMessage, Error> {
+    /// Stages the record passed through with when each was reached (UTC), oldest first.
+    pub async fn get_stage_history(&self) -> Result<Vec<(MemberJoinMessageStage, String)>, Error> {
         let conn = Self::get_connection().await?;
         let mut rows = conn.query(
-            "SELECT * FROM member_join_messages WHERE discord_user_id = ?1",
-            [discord_user_id]
+            "SELECT stage, changed_at FROM stage_changes WHERE record_id = ?1 ORDER BY id",
+            (self.id,)
+        ).await?;
+
+        let mut history = Vec::new();
+        while let Some(row) = rows.next().await? {
+            history.push((
+                MemberJoinMessageStage::from(*row.get_value(0)?.as_integer().expect("Could not get stage from db.")),
+                row.get_value(1)?.as_text().cloned().unwrap_or_default(),
+            ));
+        }
+
+        Ok(history)
+    }
+
+    pub async fn update_details(&self, character_name: String, realm_name: String, chapter_name: String, flavour: Flavour, faction: Option<Faction>) -> Result<(), Error> {
+        let conn = Self::get_connection().await?;
+        let faction = faction.map(|faction| faction.key().to_string()).unwrap_or_default();
+        conn.execute(
+            "UPDATE member_join_messages SET character_name = ?1, realm_name = ?2, chapter_name = ?3, flavour = ?4, faction = ?5 WHERE id = ?6",
+            [character_name, realm_name, chapter_name, flavour.key().to_string(), faction, self.id.to_string()]
+        ).await?;
+
+        Ok(())
+    }
+
+    /// Forgets everything the member submitted, so the record looks like a fresh join.
+    pub async fn clear_details(&self) -> Result<(), Error> {
+        let conn = Self::get_connection().await?;
+        conn.execute(
+            "UPDATE member_join_messages SET character_name = '', realm_name = '', chapter_name = '', flavour = '', faction = '', requested_chapter = '' WHERE id = ?1",
+            [self.id.to_string()]
+        ).await?;
+
+        Ok(())
+    }
+
+    /// Records the chapter the member asked to move to. An empty name clears the request.
+    pub async fn set_requested_chapter(&self, chapter_name: String) -> Result<(), Error> {
+        let conn = Self::get_connection().await?;
+        conn.execute(
+            "UPDATE member_join_messages SET requested_chapter = ?1 WHERE id = ?2",
+            [chapter_name, self.id.to_string()]
+        ).await?;
+
+        Ok(())
+    }
+
+    /// Points every record of a guild that names the old chapter at the new one. Returns the number of records changed.
+    pub async fn rename_chapter(guild_id: String, old_chapter_name: String, new_chapter_name: String) -> Result<u64, Error> {
+        let conn = Self::get_connection().await?;
+        let changed = conn.execute(
+            "UPDATE member_join_messages SET chapter_name = ?1 WHERE guild_id = ?2 AND chapter_name = ?3",
+            [new_chapter_name.clone(), guild_id.clone(), old_chapter_name.clone()]
+        ).await?;
+        conn.execute(
+            "UPDATE member_join_messages SET requested_chapter = ?1 WHERE guild_id = ?2 AND requested_chapter = ?3",
+            [new_chapter_name.clone(), guild_id.clone(), old_chapter_name.clone()]
+        ).await?;
+        conn.execute(
+            "UPDATE chapter_waitlist SET chapter_name = ?1 WHERE guild_id = ?2 AND chapter_name = ?3",
+            [new_chapter_name, guild_id, old_chapter_name]
+        ).await?;
+
+        Ok(changed)
+    }
+
+    /// Moves a merged chapter's records into another chapter. The records take on that chapter's flavour,
+    /// as the merge may cross game versions. Returns how many records moved.
+    pub async fn merge_chapter(guild_id: String, from_chapter_name: String, into_chapter_name: String, flavour: Flavour) -> Result<u64, Error> {
+        let conn = Self::get_connection().await?;
+        // Records without a flavour have not submitted the form yet, so they keep none.
+        conn.execute(
+            "UPDATE member_join_messages SET flavour = ?1 WHERE guild_id = ?2 AND chapter_name = ?3 AND flavour != ''",
+            [flavour.key().to_string(), guild_id.clone(), from_chapter_name.clone()]
+        ).await?;
+
+        Self::rename_chapter(guild_id, from_chapter_name, into_chapter_name).await
+    }
+
+    /// Counts the members a chapter has taken on: those onboarding or completed, each counted once.
+    pub async fn count_in_chapter(guild_id: String, chapter_name: String) -> Result<i64, Error> {
+        let conn = Self::get_connection().await?;
+        let mut rows = conn.query(
+            "SELECT DISTINCT discord_user_id FROM member_join_messages WHERE guild_id = ?1 AND chapter_name = ?2 AND stage IN (1, 2)",
+            [guild_id, chapter_name]
+        ).await?;
+
+        let mut count = 0;
+        while rows.next().await?.is_some() {
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    /// Marks a departed member's records as left. Returns the chapters they were counted in.
+    pub async fn mark_left(guild_id: String, discord_user_id: String) -> Result<Vec<String>, Error> {
+        let conn = Self::get_connection().await?;
+        let mut rows = conn.query(
+            "SELECT DISTINCT chapter_name FROM member_join_messages WHERE guild_id = ?1 AND discord_user_id = ?2 AND stage IN (1, 2) AND chapter_name != ''",
+            [guild_id.clone(), discord_user_id.clone()]
+        ).await?;
+
+        let mut chapter_names = Vec::new();
+        while let Some(row) = rows.next().await? {
+            chapter_names.push(row.get_value(0)?.as_text().cloned().unwrap_or_default());
+        }
+
+        conn.execute(
+            "INSERT INTO stage_changes (record_id, stage, changed_at) \
+            SELECT id, ?1, CURRENT_TIMESTAMP FROM member_join_messages WHERE guild_id = ?2 AND discord_user_id = ?3 AND stage IN (0, 1, 2)",
+            (MemberJoinMessageStage::Left as i32, guild_id.clone(), discord_user_id.clone())
+        ).await?;
+
+        conn.execute(
+            "UPDATE member_join_messages SET stage = ?1 WHERE guild_id = ?2 AND discord_user_id = ?3 AND stage IN (0, 1, 2)",
+            [MemberJoinMessageStage::Left.to_string(), guild_id, discord_user_id]
+        ).await?;
+
+        Ok(chapter_names)
+    }
+
+    pub async fn get_message_by_discord_user_id(guild_id: String, discord_user_id: String) -> Result<MemberJoinMessage, Error> {
+        let conn = Self::get_connection().await?;
+        let mut rows = conn.query(
+            "SELECT id, discord_user_id, message_id, stage, character_name, realm_name, chapter_name, flavour, faction, invite_code, invited_by, requested_chapter FROM member_join_messages WHERE guild_id = ?1 AND discord_user_id = ?2",
+            [guild_id, discord_user_id]
         ).await?;
 
         let join_message = Self::collect_from_db(&mut rows).await?;
@@ -103,7 +365,7 @@ impl MemberJoinMessage {
     pub async fn get_message_by_message_id(message_id: String) -> Result<MemberJoinMessage, Error> {
         let conn = Self::get_connection().await?;
         let mut rows = conn.query(
-            "SELECT * FROM member_join_messages WHERE message_id = ?1",
+            "SELECT id, discord_user_id, message_id, stage, character_name, realm_name, chapter_name, flavour, faction, invite_code, invited_by, requested_chapter FROM member_join_messages WHERE message_id = ?1",
             [message_id]
         ).await?;
 
@@ -112,22 +374,281 @@ impl MemberJoinMessage {
         Ok(join_message)
     }
 
+    pub async fn get_all(guild_id: String) -> Result<Vec<MemberJoinMessage>, Error> {
+        let conn = Self::get_connection().await?;
+        let mut rows = conn.query(
+            "SELECT id, discord_user_id, message_id, stage, character_name, realm_name, chapter_name, flavour, faction, invite_code, invited_by, requested_chapter FROM member_join_messages WHERE guild_id = ?1 ORDER BY id",
+            [guild_id]
+        ).await?;
+
+        let mut join_messages = Vec::new();
+        while let Some(row) = rows.next().await? {
+            join_messages.push(Self::from_row(&row)?);
+        }
+
+        Ok(join_messages)
+    }
+
+    /// Keeps only each member's latest record; earlier ones are from before they left and rejoined.
+    pub fn latest_per_member(records: Vec<MemberJoinMessage>) -> Vec<MemberJoinMessage> {
+        let mut latest: Vec<MemberJoinMessage> = Vec::new();
+        for record in records {
+            latest.retain(|previous| previous.discord_user_id != record.discord_user_id);
+            latest.push(record);
+        }
+
+        latest
+    }
+
     async fn collect_from_db(rows: &mut Rows) -> Result<MemberJoinMessage, Error> {
-        let mut join_message = MemberJoinMessage {
-            id: 0,
-            discord_user_id: 0,
-            message_id: 0,
-            stage: MemberJoinMessageStage::NewMember,
-        };
+        let mut join_message = None;
+        while let Some(row) = rows.next().await? {
+            join_message = Some(Self::from_row(&row)?);
+        }
+
+        join_message.ok_or(Error::QueryReturnedNoRows)
+    }
+
+    fn from_row(row: &Row) -> Result<MemberJoinMessage, Error> {
+        Ok(MemberJoinMessage {
+            id: *row.get_value(0)?.as_integer().expect("Could not get ID from db."),
+            discord_user_id: row.get_value(1)?.as_text().expect("Could not get Discord User ID from db.").parse::<u64>().expect("Could not parse discord id as u64 from db."),
+            message_id: row.get_value(2)?.as_text().expect("Could not get Message ID from db.").parse::<u64>().expect("Could not parse message id as u64 from db."),
+            stage: MemberJoinMessageStage::from(*row.get_value(3)?.as_integer().expect("Could not get stage from db.")),
+            character_name: row.get_value(4)?.as_text().cloned().unwrap_or_default(),
+            realm_name: row.get_value(5)?.as_text().cloned().unwrap_or_default(),
+            chapter_name: row.get_value(6)?.as_text().cloned().unwrap_or_default(),
+            flavour: row.get_value(7)?.as_text().and_then(|key| Flavour::from_key(key)),
+            faction: row.get_value(8)?.as_text().and_then(|key| Faction::from_key(key)),
+            invite_code: row.get_value(9)?.as_text().filter(|code| !code.is_empty()).cloned(),
+            invited_by: row.get_value(10)?.as_text().and_then(|user_id| user_id.parse::<u64>().ok()),
+            requested_chapter: row.get_value(11)?.as_text().filter(|chapter_name| !chapter_name.is_empty()).cloned(),
+        })
+    }
+}
+
+/// A single questionnaire answer. Answers are keyed by the questionnaire field id, so new
+/// questions can be added to questionnaire.json without touching the schema.
+#[derive(Debug, Clone)]
+pub struct MemberAnswer {
+    pub field_id: String,
+    pub value: String,
+}
+
+impl MemberAnswer {
+    pub async fn save_answer(guild_id: String, discord_user_id: String, field_id: String, value: String) -> Result<(), Error> {
+        let conn = MemberJoinMessage::get_connection().await?;
+        conn.execute(
+            "DELETE FROM member_answers WHERE guild_id = ?1 AND discord_user_id = ?2 AND field_id = ?3",
+            [guild_id.clone(), discord_user_id.clone(), field_id.clone()]
+        ).await?;
+        conn.execute(
+            "INSERT INTO member_answers (guild_id, discord_user_id, field_id, value) VALUES (?1, ?2, ?3, ?4)",
+            [guild_id, discord_user_id, field_id, value]
+        ).await?;
+
+        Ok(())
+    }
+
+    pub async fn get_answers(guild_id: String, discord_user_id: String) -> Result<Vec<MemberAnswer>, Error> {
+        let conn = MemberJoinMessage::get_connection().await?;
+        let mut rows = conn.query(
+            "SELECT field_id, value FROM member_answers WHERE guild_id = ?1 AND discord_user_id = ?2",
+            [guild_id, discord_user_id]
+        ).await?;
 
+        let mut answers = Vec::new();
         while let Some(row) = rows.next().await? {
-            join_message.id = *row.get_value(0)?.as_integer().expect("Could not get ID from db.");
-            join_message.discord_user_id = row.get_value(1)?.as_text().expect("Could not get Discord User ID from db.").parse::<u64>().expect("Could not parse discord id as u64 from db.");
-            join_message.message_id = row.get_value(2)?.as_text().expect("Could not get Message ID from db.").parse::<u64>().expect("Could not parse message id as u64 from db.");
-            let out_stage = row.get_value(3)?.as_text().expect("Could not get stage from db.").parse::<i64>().expect("Could not parse stage as i64.");
-            join_message.stage = MemberJoinMessageStage::from(out_stage);
+            answers.push(MemberAnswer {
+                field_id: row.get_value(0)?.as_text().cloned().unwrap_or_default(),
+                value: row.get_value(1)?.as_text().cloned().unwrap_or_default(),
+            });
         }
 
-        Ok(join_message)
+        Ok(answers)
+    }
+
+    pub async fn delete_answers(guild_id: String, discord_user_id: String) -> Result<(), Error> {
+        let conn = MemberJoinMessage::get_connection().await?;
+        conn.execute(
+            "DELETE FROM member_answers WHERE guild_id = ?1 AND discord_user_id = ?2",
+            [guild_id, discord_user_id]
+        ).await?;
+
+        Ok(())
+    }
+
+    pub fn find<'a>(answers: &'a [MemberAnswer], field_id: &str) -> Option<&'a str> {
+        answers.iter().find(|answer| answer.field_id == field_id).map(|answer| answer.value.as_str())
+    }
+}
+
+/// A member's acceptance of a published version of the guild rules. Every acceptance is kept
+/// so the history of re-acceptances is preserved.
+#[derive(Debug, Clone)]
+pub struct RulesAcceptance {
+    pub version: i64,
+}
+
+impl RulesAcceptance {
+    pub async fn record(guild_id: String, discord_user_id: String, version: i64, accepted_at: String) -> Result<(), Error> {
+        let conn = MemberJoinMessage::get_connection().await?;
+        conn.execute(
+            "INSERT INTO rules_acceptances (guild_id, discord_user_id, version, accepted_at) VALUES (?1, ?2, ?3, ?4)",
+            [guild_id, discord_user_id, version.to_string(), accepted_at]
+        ).await?;
+
+        Ok(())
+    }
+
+    pub async fn get_latest(guild_id: String, discord_user_id: String) -> Result<RulesAcceptance, Error> {
+        let conn = MemberJoinMessage::get_connection().await?;
+        let mut rows = conn.query(
+            "SELECT version FROM rules_acceptances WHERE guild_id = ?1 AND discord_user_id = ?2 ORDER BY version DESC LIMIT 1",
+            [guild_id, discord_user_id]
+        ).await?;
+
+        match rows.next().await? {
+            Some(row) => Ok(RulesAcceptance {
+                version: *row.get_value(0)?.as_integer().expect("Could not get rules version from db."),
+            }),
+            None => Err(Error::QueryReturnedNoRows),
+        }
+    }
+}
+
+/// A posted copy of the welcome message, tracked so it can be edited when chapters change.
+#[derive(Debug, Clone)]
+pub struct WelcomeMessage {
+    pub id: i64,
+    pub guild_id: u64,
+    pub channel_id: u64,
+    pub message_id: u64,
+}
+
+impl WelcomeMessage {
+    pub async fn push_message(guild_id: String, channel_id: String, message_id: String) -> Result<(), Error> {
+        let conn = MemberJoinMessage::get_connection().await?;
+        conn.execute(
+            "INSERT INTO welcome_messages (guild_id, channel_id, message_id) VALUES (?1, ?2, ?3)",
+            [guild_id, channel_id, message_id]
+        ).await?;
+
+        Ok(())
+    }
+
+    pub async fn get_all() -> Result<Vec<WelcomeMessage>, Error> {
+        let conn = MemberJoinMessage::get_connection().await?;
+        let mut rows = conn.query(
+            "SELECT id, guild_id, channel_id, message_id FROM welcome_messages",
+            ()
+        ).await?;
+
+        let mut messages = Vec::new();
+        while let Some(row) = rows.next().await? {
+            messages.push(WelcomeMessage {
+                id: *row.get_value(0)?.as_integer().expect("Could not get ID from db."),
+                guild_id: row.get_value(1)?.as_text().expect("Could not get Guild ID from db.").parse::<u64>().expect("Could not parse guild id as u64 from db."),
+                channel_id: row.get_value(2)?.as_text().expect("Could not get Channel ID from db.").parse::<u64>().expect("Could not parse channel id as u64 from db."),
+                message_id: row.get_value(3)?.as_text().expect("Could not get Message ID from db.").parse::<u64>().expect("Could not parse message id as u64 from db."),
+            });
+        }
+
+        Ok(messages)
+    }
+
+    pub async fn delete(&self) -> Result<(), Error> {
+        let conn = MemberJoinMessage::get_connection().await?;
+        conn.execute(
+            "DELETE FROM welcome_messages WHERE id = ?1",
+            [self.id.to_string()]
+        ).await?;
+
+        Ok(())
+    }
+}
+
+/// A member queued for a chapter that was closed or full when they picked it. A member waits
+/// for one chapter at a time; the oldest entries are told first when a spot opens.
+#[derive(Debug, Clone)]
+pub struct WaitlistEntry {
+    pub id: i64,
+    pub discord_user_id: u64,
+    /// Locale of the member's client when they joined the waitlist, for the notification.
+    pub locale: String,
+}
+
+impl WaitlistEntry {
+    /// Queues the member for the chapter, replacing any earlier entry of theirs. Returns their place in the queue.
+    pub async fn add(guild_id: String, discord_user_id: String, chapter_name: String, locale: String, added_at: String) -> Result<usize, Error> {
+        let existing = Self::get_for_chapter(guild_id.clone(), chapter_name.clone()).await?;
+        if let Some(position) = existing.iter().position(|entry| entry.discord_user_id.to_string() == discord_user_id) {
+            return Ok(position + 1);
+        }
+
+        Self::remove_member(guild_id.clone(), discord_user_id.clone()).await?;
+        let conn = MemberJoinMessage::get_connection().await?;
+        conn.execute(
+            "INSERT INTO chapter_waitlist (guild_id, discord_user_id, chapter_name, locale, added_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            [guild_id, discord_user_id, chapter_name, locale, added_at]
+        ).await?;
+
+        Ok(existing.len() + 1)
+    }
+
+    /// The chapter's queue, oldest first.
+    pub async fn get_for_chapter(guild_id: String, chapter_name: String) -> Result<Vec<WaitlistEntry>, Error> {
+        let conn = MemberJoinMessage::get_connection().await?;
+        let mut rows = conn.query(
+            "SELECT id, discord_user_id, locale FROM chapter_waitlist WHERE guild_id = ?1 AND chapter_name = ?2 ORDER BY id",
+            [guild_id, chapter_name]
+        ).await?;
+
+        let mut entries = Vec::new();
+        while let Some(row) = rows.next().await? {
+            entries.push(WaitlistEntry {
+                id: *row.get_value(0)?.as_integer().expect("Could not get ID from db."),
+                discord_user_id: row.get_value(1)?.as_text().expect("Could not get Discord User ID from db.").parse::<u64>().expect("Could not parse discord id as u64 from db."),
+                locale: row.get_value(2)?.as_text().cloned().unwrap_or_default(),
+            });
+        }
+
+        Ok(entries)
+    }
+
+    /// Takes the member off every waitlist in the guild.
+    pub async fn remove_member(guild_id: String, discord_user_id: String) -> Result<(), Error> {
+        let conn = MemberJoinMessage::get_connection().await?;
+        conn.execute(
+            "DELETE FROM chapter_waitlist WHERE guild_id = ?1 AND discord_user_id = ?2",
+            [guild_id, discord_user_id]
+        ).await?;
+
+        Ok(())
     }
-}
\ No newline at end of file
+
+    pub async fn delete(&self) -> Result<(), Error> {
+        let conn = MemberJoinMessage::get_connection().await?;
+        conn.execute(
+            "DELETE FROM chapter_waitlist WHERE id = ?1",
+            [self.id.to_string()]
+        ).await?;
+
+        Ok(())
+    }
+}
+
+/// Audit log of settings changed with `/config`.
+pub struct ConfigChange;
+
+impl ConfigChange {
+    pub async fn record(guild_id: String, discord_user_id: String, setting: String, old_value: String, new_value: String, changed_at: String) -> Result<(), Error> {
+        let conn = MemberJoinMessage::get_connection().await?;
+        conn.execute(
+            "INSERT INTO config_changes (guild_id, discord_user_id, setting, old_value, new_value, changed_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            [guild_id, discord_user_id, setting, old_value, new_value, changed_at]
+        ).await?;
+
+        Ok(())
+    }
+}
@@ -1,28 +1,44 @@
 use std::fmt::{format, Debug};
 use std::sync::RwLock;
-use serenity::all::{ButtonStyle, Channel, ChannelId, ComponentInteraction, CreateEmbedAuthor, CreateEmbedFooter, GuildId, Member, MessageId, ModalInteraction};
-use serenity::builder::{CreateButton, CreateEmbed, CreateMessage, EditMessage};
+use serenity::all::{ButtonStyle, Channel, ChannelId, ComponentInteraction, CreateEmbedAuthor, CreateEmbedFooter, Embed, GuildId, InputTextStyle, Member, Message, MessageId, ModalInteraction, RoleId, User, UserId};
+use serenity::builder::{CreateActionRow, CreateAllowedMentions, CreateButton, CreateEmbed, CreateInputText, CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage, CreateModal, EditMessage};
 use serenity::client;
 use serenity::model::Timestamp;
 use turso::{
     Builder,
     Connection,
     Error};
-use crate::emojis::{emoji_counterclockwise_arrows, emoji_party_popper, emoji_warning};
-use crate::member_db::{MemberJoinMessage, MemberJoinMessageStage};
-use crate::secrets;
+use crate::emojis::emoji_warning;
+use crate::invites::{self, UsedInvite};
+use crate::chapters::{Chapter, Chapters, Faction};
+use crate::member_db::{MemberAnswer, MemberJoinMessage, MemberJoinMessageStage, WaitlistEntry};
+use crate::nickname::apply_nickname_policy;
+use crate::nmi_handler::modal_input_value;
+use crate::permissions::{authorize_component, authorize_modal, Action};
+use crate::config;
+use crate::i18n;
+use crate::questionnaire::{Questionnaire, FIELD_CHARACTER_NAME, FIELD_REALM_NAME};
+use crate::templates::Templates;
+use crate::waitlist;
+
+// Card field showing a member's open `/me chapter` request.
+const REQUESTED_CHAPTER_FIELD: &str = "Requested Chapter";
 
 pub async fn handle_member_join(ctx: &client::Context, new_member: &Member) -> Result<(), serenity::Error> {
-    let secrets = secrets::Secrets::get_secrets();
-    let channel_id = ctx.http.get_channel(ChannelId::new(secrets.nmi_channel_id)).await?;
+    let config = config::Config::for_guild(new_member.guild_id);
+    let channel_id = ctx.http.get_channel(ChannelId::new(config.nmi_channel_id)).await?;
 
-    let joined_message = create_joined_message(new_member.clone());
+    let invite = invites::detect_used_invite(ctx, new_member.guild_id).await;
+    let joined_message = create_joined_message(new_member.clone(), invite.as_ref());
     let message = channel_id.id().send_message(&ctx.http, joined_message).await?;
 
     let message_result = MemberJoinMessage::push_message(
+        new_member.guild_id.to_string(),
         new_member.user.id.to_string(),
         message.id.to_string(),
-        MemberJoinMessageStage::NewMember
+        MemberJoinMessageStage::NewMember,
+        invite.as_ref().map(|invite| invite.code.clone()).unwrap_or_default(),
+        invite.as_ref().and_then(|invite| invite.inviter_id).map(|inviter_id| inviter_id.to_string()).unwrap_or_default(),
     ).await;
 
     match message_result {
@@ -37,19 +53,54 @@ pub async fn handle_member_join(ctx: &client::Context, new_member: &Member) -> R
     Ok(())
 }
 
-pub async fn push_member_completion_message(ctx: &client::Context, new_member: &Member, channel: Channel, new_embeds: Vec<CreateEmbed>, new_buttons: Vec<CreateButton>) -> Result<(), serenity::Error> {
-    let previous_message_result = MemberJoinMessage::get_message_by_discord_user_id(new_member.user.id.to_string()).await;
+/// Frees the departed member's chapter spot for the waitlist and takes them off any waitlist.
+pub async fn handle_member_leave(ctx: &client::Context, guild_id: GuildId, user: &User) {
+    if let Err(e) = WaitlistEntry::remove_member(guild_id.to_string(), user.id.to_string()).await {
+        println!("Error removing departed member from waitlists: {}", e);
+    }
+
+    match MemberJoinMessage::mark_left(guild_id.to_string(), user.id.to_string()).await {
+        Ok(chapter_names) => {
+            for chapter_name in chapter_names {
+                waitlist::notify_open_slots(ctx, guild_id, &chapter_name).await;
+            }
+        }
+        Err(e) => {
+            println!("Error marking departed member in database: {}", e);
+        }
+    }
+}
+
+pub async fn push_member_completion_message(ctx: &client::Context, new_member: &Member, channel: Channel, chapter: &Chapter, new_embeds: Vec<CreateEmbed>, new_buttons: Vec<CreateButton>) -> Result<(), serenity::Error> {
+    let previous_message_result = MemberJoinMessage::get_message_by_discord_user_id(new_member.guild_id.to_string(), new_member.user.id.to_string()).await;
     let previous_message: MemberJoinMessage;
 
     match previous_message_result {
         Ok(message) => {
             previous_message = message;
-            let mut previous_message_id = ctx.http.get_message(channel.id(), MessageId::new(previous_message.message_id)).await?;
-            let mut edited_msg = EditMessage::new().embeds(new_embeds);
-            for button in new_buttons {
-                edited_msg = edited_msg.button(button);
-            }
-            previous_message_id.edit(&ctx.http, edited_msg).await?;
+            let card = match ctx.http.get_message(channel.id(), MessageId::new(previous_message.message_id)).await {
+                Ok(mut previous_message_id) => {
+                    let mut edited_msg = EditMessage::new().content(officer_mention(chapter)).embeds(new_embeds);
+                    for button in new_buttons {
+                        edited_msg = edited_msg.button(button);
+                    }
+                    previous_message_id.edit(&ctx.http, edited_msg).await?;
+                    previous_message_id
+                }
+                // The join card was deleted, so the submitted card takes its place.
+                Err(e) if is_deleted(&e) => {
+                    let mut new_message = CreateMessage::new().content(officer_mention(chapter)).embeds(new_embeds);
+                    for button in new_buttons {
+                        new_message = new_message.button(button);
+                    }
+                    let card = channel.id().send_message(&ctx.http, new_message).await?;
+                    if let Err(e) = previous_message.set_message_id(card.id.to_string()).await {
+                        println!("Error storing new card in database: {}", e);
+                    }
+                    card
+                }
+                Err(e) => return Err(e),
+            };
             let result = previous_message.update_message(MemberJoinMessageStage::Onboarding).await;
             match result {
                 Ok(_) => {
@@ -59,14 +110,33 @@ pub async fn push_member_completion_message(ctx: &client::Context, new_member: &
                     println!("Error updating previous message in database: {}", e);
                 }
             }
+            // The submission is stored by now, so a failed ping is only logged.
+            if let Err(e) = notify_chapter_officers(ctx, new_member.guild_id, &card, chapter, new_member.user.id.get()).await {
+                println!("Error notifying chapter officers: {}", e);
+            }
         }
         Err(e) => {
+            // Members who joined while the bot was offline have no record yet, so start one with this card.
             println!("Error getting previous message from database: {}", e);
-            let mut new_message = CreateMessage::new().embeds(new_embeds);
+            let mut new_message = CreateMessage::new().content(officer_mention(chapter)).embeds(new_embeds);
             for button in new_buttons {
                 new_message = new_message.button(button);
             }
-            channel.id().send_message(&ctx.http, new_message).await?;
+            let card = channel.id().send_message(&ctx.http, new_message).await?;
+            let result = MemberJoinMessage::push_message(
+                new_member.guild_id.to_string(),
+                new_member.user.id.to_string(),
+                card.id.to_string(),
+                MemberJoinMessageStage::Onboarding,
+                String::new(),
+                String::new(),
+            ).await;
+            if let Err(e) = result {
+                println!("Error pushing message to database: {}", e);
+            }
+            if let Err(e) = notify_chapter_officers(ctx, new_member.guild_id, &card, chapter, new_member.user.id.get()).await {
+                println!("Error notifying chapter officers: {}", e);
+            }
         }
     }
 
@@ -75,19 +145,25 @@ pub async fn push_member_completion_message(ctx: &client::Context, new_member: &
 
 // TODO: Pass channel in instead.
 pub async fn handle_complete_onboarding(ctx: &client::Context, interaction: ComponentInteraction) -> Result<(), serenity::Error> {
-    let message_id = interaction.message.id;
-    let message_result = MemberJoinMessage::get_message_by_message_id(message_id.to_string()).await;
+    let guild_id = config::Config::guild_or_primary(interaction.guild_id);
+    let chapter = card_chapter(guild_id, &interaction.message).await;
+    if !authorize_component(ctx, &interaction, Action::Complete, chapter.as_ref()).await? {
+        return Ok(());
+    }
+
+    // The pressed card is updated even without a record, rather than posting an untracked copy.
+    let mut card = *interaction.message.clone();
+    let mut edit_message = EditMessage::new()
+        .embeds(create_completed_onboarding_embeds(&interaction));
+    let buttons = create_completed_onboarding_buttons(guild_id);
+    for button in buttons {
+        edit_message = edit_message.button(button);
+    }
+    card.edit(&ctx.http, edit_message).await?;
 
+    let message_result = MemberJoinMessage::get_message_by_message_id(card.id.to_string()).await;
     match message_result {
         Ok(message) => {
-            let mut message_id = ctx.http.get_message(interaction.channel_id, message_id).await?;
-            let mut edit_message = EditMessage::new()
-                .embeds(create_completed_onboarding_embeds(&interaction));
-            let buttons = create_completed_onboarding_buttons();
-            for button in buttons {
-                edit_message = edit_message.button(button);
-            }
-            message_id.edit(&ctx.http, edit_message).await?;
             let result = message.update_message(MemberJoinMessageStage::Completed).await;
             match result {
                 Ok(_) => {
@@ -100,12 +176,6 @@ pub async fn handle_complete_onboarding(ctx: &client::Context, interaction: Comp
         }
         Err(e) => {
             println!("Error getting message from database: {}", e);
-            let mut new_message = CreateMessage::new().embeds(create_completed_onboarding_embeds(&interaction));
-            let buttons = create_completed_onboarding_buttons();
-            for button in buttons {
-                new_message = new_message.button(button);
-            }
-            interaction.channel_id.send_message(&ctx.http, new_message).await?;
         }
     }
 
@@ -113,19 +183,24 @@ pub async fn handle_complete_onboarding(ctx: &client::Context, interaction: Comp
 }
 
 pub async fn handle_undo_completion(ctx: &client::Context, interaction: ComponentInteraction) -> Result<(), serenity::Error> {
-    let message_id = interaction.message.id;
-    let message_result = MemberJoinMessage::get_message_by_message_id(message_id.to_string()).await;
+    let guild_id = config::Config::guild_or_primary(interaction.guild_id);
+    let chapter = card_chapter(guild_id, &interaction.message).await;
+    if !authorize_component(ctx, &interaction, Action::Undo, chapter.as_ref()).await? {
+        return Ok(());
+    }
+
+    let mut card = *interaction.message.clone();
+    let mut edit_message = EditMessage::new()
+        .embeds(create_undo_onboarding_embeds(&interaction));
+    let buttons = create_undo_member_buttons(guild_id);
+    for button in buttons {
+        edit_message = edit_message.button(button);
+    }
+    card.edit(&ctx.http, edit_message).await?;
 
+    let message_result = MemberJoinMessage::get_message_by_message_id(card.id.to_string()).await;
     match message_result {
         Ok(message) => {
-            let mut message_id = ctx.http.get_message(interaction.channel_id, message_id).await?;
-            let mut edit_message = EditMessage::new()
-                .embeds(create_undo_onboarding_embeds(&interaction));
-            let buttons = create_undo_member_buttons();
-            for button in buttons {
-                edit_message = edit_message.button(button);
-            }
-            message_id.edit(&ctx.http, edit_message).await?;
             let result = message.update_message(MemberJoinMessageStage::Onboarding).await;
             match result {
                 Ok(_) => {
@@ -138,148 +213,887 @@ pub async fn handle_undo_completion(ctx: &client::Context, interaction: Componen
         }
         Err(e) => {
             println!("Error getting message from database: {}", e);
-            let mut new_message = CreateMessage::new().embeds(create_undo_onboarding_embeds(&interaction));
-            let buttons = create_undo_member_buttons();
-            for button in buttons {
-                new_message = new_message.button(button);
-            }
-            interaction.channel_id.send_message(&ctx.http, new_message).await?;
         }
     }
 
     Ok(())
 }
 
-pub fn create_joined_message(new_member: Member) -> CreateMessage {
-    let info_author = CreateEmbedAuthor::new("New Member Joined");
+pub fn create_joined_message(new_member: Member, invite: Option<&UsedInvite>) -> CreateMessage {
+    let message = CreateMessage::new()
+        .embed(create_joined_embed(new_member.guild_id, new_member.user.id.get(), invite, "status_awaiting_onboarding"));
 
-    let timestamp: Timestamp = Timestamp::now();
+    message
+}
 
-    let footer = CreateEmbedFooter::new(format!("Member ID: {}", new_member.user.id));
+/// The card of a member who has not submitted the chapter form, with the status named by `status_key`.
+fn create_joined_embed(guild_id: GuildId, discord_user_id: u64, invite: Option<&UsedInvite>, status_key: &str) -> CreateEmbed {
+    let templates = Templates::for_guild(guild_id);
+    let vars = [("member", format!("<@{}>", discord_user_id))];
 
-    let info_embed = CreateEmbed::new()
-        .author(info_author)
-        .field("Member", format!("<@{}>", new_member.user.id), true)
+    let timestamp: Timestamp = Timestamp::now();
+
+    let mut info_embed = templates.message("member_joined").embed(&vars)
+        .field("Member", format!("<@{}>", discord_user_id), true)
         .field("Character Name", emoji_warning(), true)
         .field("Realm", emoji_warning(), true)
-        .field("User Id", new_member.user.id.to_string(), true)
-        .field("Status", format!("{} Awaiting Onboarding", emoji_counterclockwise_arrows()), true)
+        .field("User Id", discord_user_id.to_string(), true)
+        .field("Status", templates.text(status_key, &vars), true)
         .timestamp(timestamp);
+    if let Some(invite) = invite {
+        let chapter_name = UsedInvite::chapter_name(guild_id, &invite.code);
+        let invite_vars = [
+            ("code", invite.code.clone()),
+            ("inviter", invite.inviter_id.map(|inviter_id| format!("<@{}>", inviter_id)).unwrap_or_else(|| templates.text("inviter_unknown", &[]))),
+            ("chapter", chapter_name.clone().unwrap_or_default()),
+        ];
+        let invited_via = match chapter_name {
+            Some(_) => templates.text("invited_via_chapter", &invite_vars),
+            None => templates.text("invited_via", &invite_vars),
+        };
+        info_embed = info_embed.field("Invited via", invited_via, false);
+    }
 
-    let message = CreateMessage::new()
-        .embed(info_embed);
+    info_embed
+}
 
-    message
+pub fn create_new_member_embeds(guild_id: GuildId, discord_user_id: u64, character_name: String, realm: String, chapter: &Chapter, faction: Option<Faction>, extra_answers: Vec<(String, String)>) -> Vec<CreateEmbed> {
+    let info_embed = create_officer_card_embed(guild_id, discord_user_id, character_name, realm, chapter, faction, "status_awaiting_approval")
+        .fields(extra_answers.into_iter().map(|(name, value)| (name, value, false)));
+
+    vec![info_embed]
 }
 
-pub fn create_new_member_embeds(interaction: &ModalInteraction, discord_user_id: u64, character_name: String, realm: String) -> Vec<CreateEmbed> {
-    let info_author = CreateEmbedAuthor::new("Member Onboarding Submitted");
+/// The card of a member who submitted the chapter form, without their extra answers.
+fn create_officer_card_embed(guild_id: GuildId, discord_user_id: u64, character_name: String, realm: String, chapter: &Chapter, faction: Option<Faction>, status_key: &str) -> CreateEmbed {
+    let templates = Templates::for_guild(guild_id);
+    let vars = [
+        ("member", format!("<@{}>", discord_user_id)),
+        ("character", character_name.clone()),
+        ("realm", realm.clone()),
+        ("chapter", chapter.name.clone()),
+    ];
 
     let timestamp: Timestamp = Timestamp::now();
 
-    let footer = CreateEmbedFooter::new(format!("Member ID: {}", interaction.user.id.to_string()));
-
-    let info_embed = CreateEmbed::new()
-        .author(info_author)
-        .title(format!("{} IMPORTANT REMINDER", emoji_warning()))
-        .description("Warning! Only mark complete after promoting this member in-game to full member status.")
-        .field("Member", format!("<@{}>", interaction.user.id), true)
+    let mut info_embed = templates.message("officer_card").embed(&vars)
+        .field("Member", format!("<@{}>", discord_user_id), true)
         .field("Character Name", character_name, true)
         .field("Realm", realm, true)
         .field("User Id", discord_user_id.to_string(), true)
-        .field("Status", format!("{} Awaiting Officer Approval", emoji_counterclockwise_arrows()), true)
-        //.footer(footer)
-        .timestamp(timestamp);
+        .field("Status", templates.text(status_key, &vars), true)
+        .field("Chapter", chapter.name.clone(), true)
+        .field("Game", templates.text(&chapter.flavour.label_key(), &[]), true);
+    if let Some(faction) = faction {
+        info_embed = info_embed.field("Faction", templates.text(&faction.label_key(), &[]), true);
+    }
 
-    vec![info_embed]
+    info_embed.timestamp(timestamp)
+}
+
+/// Labels and values of the questionnaire answers beyond character, realm and chapter, as shown on a card.
+/// The card is shared, so the labels use the guild's locale rather than the member's.
+pub fn extra_answer_fields(guild_id: GuildId, answers: &[MemberAnswer]) -> Vec<(String, String)> {
+    let guild_locale = i18n::guild_locale(guild_id);
+    Questionnaire::load().extra_fields()
+        .map(|field| (field.display_name(&guild_locale).to_string(), MemberAnswer::find(answers, &field.id).unwrap_or_default().to_string()))
+        .collect()
 }
 
-pub fn create_new_member_buttons() -> Vec<CreateButton> {
-    let button_complete_registration = CreateButton::new("button_complete_registration")
-        .style(ButtonStyle::Success)
-        .label("Mark Complete");
+pub fn create_new_member_buttons(guild_id: GuildId) -> Vec<CreateButton> {
+    let template = Templates::for_guild(guild_id).message("officer_card");
+
+    let button_complete_registration = template.button("button_complete_registration", ButtonStyle::Success, &[]);
+
+    let button_change_chapter = template.button("button_change_chapter", ButtonStyle::Secondary, &[]);
+
+    let button_change_character = template.button("button_change_character", ButtonStyle::Secondary, &[]);
 
-    let button_change_chapter = CreateButton::new("button_change_chapter")
-        .style(ButtonStyle::Secondary)
-        .label("Change Chapter");
+    let button_reject_registration = template.button("button_reject_registration", ButtonStyle::Danger, &[]);
 
-    vec![button_complete_registration, button_change_chapter]
+    vec![button_complete_registration, button_change_chapter, button_change_character, button_reject_registration]
 }
 
 pub fn create_completed_onboarding_embeds(interaction: &ComponentInteraction) -> Vec<CreateEmbed> {
-    let info_author = CreateEmbedAuthor::new("Member Onboarding Submitted");
-
     let timestamp: Timestamp = Timestamp::now();
 
-    let footer = CreateEmbedFooter::new(format!("Member ID: {}", interaction.user.id.to_string()));
-
     let message = interaction.message.clone();
 
-    let member_mention = message.embeds[0].fields[0].value.clone();
-    let character_name = message.embeds[0].fields[1].value.clone();
-    let realm = message.embeds[0].fields[2].value.clone();
-    let user_id = message.embeds[0].fields[3].value.clone();
+    let vars = [("officer", format!("<@{}>", interaction.user.id))];
 
-    let info_embed = CreateEmbed::new()
-        .author(info_author)
-        .title(format!("{} IMPORTANT REMINDER", emoji_warning()))
-        .description("Warning! Only mark complete after promoting this member in-game to full member status.")
-        .field("Member", member_mention, true)
-        .field("Character Name", character_name, true)
-        .field("Realm", realm, true)
-        .field("User Id", user_id, true)
-        .field("Status", format!("{} Onboarding Complete!", emoji_party_popper()), true)
-        //.footer(footer)
+    // Copy every field across so questionnaire answers survive the status change.
+    let templates = Templates::for_guild(config::Config::guild_or_primary(interaction.guild_id));
+    let info_embed = replace_card_field(&message.embeds[0], "Status", templates.text("status_complete", &vars))
         .timestamp(timestamp);
 
     vec![info_embed]
 }
 
-pub fn create_completed_onboarding_buttons() -> Vec<CreateButton> {
-    let button_undo_completed = CreateButton::new("button_undo_completed")
-        .style(ButtonStyle::Danger)
-        .label("Undo");
+pub fn create_completed_onboarding_buttons(guild_id: GuildId) -> Vec<CreateButton> {
+    let template = Templates::for_guild(guild_id).message("officer_card");
 
-    vec![button_undo_completed]
+    let button_undo_completed = template.button("button_undo_completed", ButtonStyle::Danger, &[]);
+
+    // Members switch mains long after onboarding, so the card keeps offering the change.
+    let button_change_character = template.button("button_change_character", ButtonStyle::Secondary, &[]);
+
+    vec![button_undo_completed, button_change_character]
 }
 
 pub fn create_undo_onboarding_embeds(interaction: &ComponentInteraction) -> Vec<CreateEmbed> {
-    let info_author = CreateEmbedAuthor::new("Member Onboarding Submitted");
-
     let timestamp: Timestamp = Timestamp::now();
 
-    let footer = CreateEmbedFooter::new(format!("Member ID: {}", interaction.user.id.to_string()));
-
     let message = interaction.message.clone();
 
-    let member_mention = message.embeds[0].fields[0].value.clone();
-    let character_name = message.embeds[0].fields[1].value.clone();
-    let realm = message.embeds[0].fields[2].value.clone();
-    let user_id = message.embeds[0].fields[3].value.clone();
+    let vars = [("officer", format!("<@{}>", interaction.user.id))];
 
-    let info_embed = CreateEmbed::new()
-        .author(info_author)
-        .title(format!("{} IMPORTANT REMINDER", emoji_warning()))
-        .description("Warning! Only mark complete after promoting this member in-game to full member status.")
-        .field("Member", member_mention, true)
-        .field("Character Name", character_name, true)
-        .field("Realm", realm, true)
-        .field("User Id", user_id, true)
-        .field("Status", format!("{} Onboarding Complete!", emoji_party_popper()), true)
-        //.footer(footer)
+    let templates = Templates::for_guild(config::Config::guild_or_primary(interaction.guild_id));
+    let info_embed = replace_card_field(&message.embeds[0], "Status", templates.text("status_awaiting_approval", &vars))
         .timestamp(timestamp);
 
     vec![info_embed]
 }
 
-pub fn create_undo_member_buttons() -> Vec<CreateButton> {
-    let button_complete_registration = CreateButton::new("button_complete_registration")
-        .style(ButtonStyle::Success)
-        .label("Mark Complete");
+pub fn create_undo_member_buttons(guild_id: GuildId) -> Vec<CreateButton> {
+    create_new_member_buttons(guild_id)
+}
+
+pub async fn handle_change_chapter(ctx: &client::Context, interaction: ComponentInteraction) -> Result<(), serenity::Error> {
+    let guild_id = config::Config::guild_or_primary(interaction.guild_id);
+    let chapter = card_chapter(guild_id, &interaction.message).await;
+    if !authorize_component(ctx, &interaction, Action::ChangeChapter, chapter.as_ref()).await? {
+        return Ok(());
+    }
+
+    let templates = Templates::for_locale(&interaction.locale);
+    let chapter_value = CreateInputText::new(
+        InputTextStyle::Short,
+        templates.text("change_chapter_label", &[]),
+        "chapter_number"
+    ).required(true).min_length(1).max_length(2).placeholder(templates.text("chapter_number_placeholder", &[]));
+
+    let modal = CreateInteractionResponse::Modal(
+        CreateModal::new("change_chapter_modal", templates.text("change_chapter_title", &[]))
+            .components(vec![CreateActionRow::InputText(chapter_value)])
+    );
+
+    interaction.create_response(&ctx.http, modal).await?;
+
+    Ok(())
+}
+
+pub async fn handle_change_chapter_response(ctx: &client::Context, interaction: &ModalInteraction) -> Result<(), serenity::Error> {
+    // Checked again on submit in case roles changed while the modal was open.
+    let guild_id = interaction.guild_id.ok_or(serenity::Error::Other("No guild ID found"))?;
+    let chapter = match &interaction.message {
+        Some(card) => card_chapter(guild_id, card).await,
+        None => None,
+    };
+    if !authorize_modal(ctx, interaction, Action::ChangeChapter, chapter.as_ref()).await? {
+        return Ok(());
+    }
+
+    let chapters = Chapters::for_guild(guild_id);
+    let templates = Templates::for_locale(&interaction.locale);
+    let chapter_number = modal_input_value(interaction, 0).parse::<usize>().unwrap_or(usize::MAX);
+    let new_chapter = match chapters.get_active_by_id(chapter_number) {
+        Some(chapter) => chapter.clone(),
+        None => {
+            interaction.create_response(
+                &ctx.http,
+                CreateInteractionResponse::Message(
+                    CreateInteractionResponseMessage::new()
+                        .content(templates.text("invalid_chapter", &[]))
+                        .ephemeral(true)
+                ),
+            ).await?;
+            return Ok(());
+        }
+    };
+
+    let mut card = *interaction.message.clone().ok_or(serenity::Error::Other("No card message found"))?;
+    let record = MemberJoinMessage::get_message_by_message_id(card.id.to_string()).await
+        .map_err(|_| serenity::Error::Other("No onboarding record found for this card"))?;
+
+    move_card_member(ctx, guild_id, &mut card, &record, &new_chapter).await?;
+
+    interaction.create_response(
+        &ctx.http,
+        CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new()
+                .content(templates.text("chapter_changed", &[
+                    ("member", format!("<@{}>", record.discord_user_id)),
+                    ("chapter", new_chapter.name.clone()),
+                    ("officer", format!("<@{}>", interaction.user.id)),
+                ]))
+                .ephemeral(true)
+        ),
+    ).await?;
+
+    Ok(())
+}
+
+/// Opens the form for changing the main character of the member on a card, filled in with the current one.
+pub async fn handle_change_character(ctx: &client::Context, interaction: ComponentInteraction) -> Result<(), serenity::Error> {
+    let guild_id = config::Config::guild_or_primary(interaction.guild_id);
+    let chapter = card_chapter(guild_id, &interaction.message).await;
+    if !authorize_component(ctx, &interaction, Action::ChangeCharacter, chapter.as_ref()).await? {
+        return Ok(());
+    }
+
+    let record = MemberJoinMessage::get_message_by_message_id(interaction.message.id.to_string()).await
+        .map_err(|_| serenity::Error::Other("No onboarding record found for this card"))?;
+    let questionnaire = Questionnaire::load();
+    let templates = Templates::for_locale(&interaction.locale);
+    let mut inputs = Vec::new();
+    for (field_id, label_key, value) in [
+        (FIELD_CHARACTER_NAME, "change_character_label", &record.character_name),
+        (FIELD_REALM_NAME, "change_realm_label", &record.realm_name),
+    ] {
+        let mut input = CreateInputText::new(InputTextStyle::Short, templates.text(label_key, &[]), field_id)
+            .required(true)
+            .value(value.clone());
+        if let Some(max_length) = questionnaire.get_field(field_id).and_then(|field| field.max_length) {
+            input = input.max_length(max_length);
+        }
+        inputs.push(CreateActionRow::InputText(input));
+    }
+
+    let modal = CreateInteractionResponse::Modal(
+        CreateModal::new("change_character_modal", templates.text("change_character_title", &[]))
+            .components(inputs)
+    );
+
+    interaction.create_response(&ctx.http, modal).await?;
+
+    Ok(())
+}
+
+pub async fn handle_change_character_response(ctx: &client::Context, interaction: &ModalInteraction) -> Result<(), serenity::Error> {
+    // Checked again on submit in case roles changed while the modal was open.
+    let guild_id = interaction.guild_id.ok_or(serenity::Error::Other("No guild ID found"))?;
+    let chapter = match &interaction.message {
+        Some(card) => card_chapter(guild_id, card).await,
+        None => None,
+    };
+    if !authorize_modal(ctx, interaction, Action::ChangeCharacter, chapter.as_ref()).await? {
+        return Ok(());
+    }
+
+    let templates = Templates::for_locale(&interaction.locale);
+    let character_name = modal_input_value(interaction, 0).trim().to_string();
+    let realm_name = modal_input_value(interaction, 1).trim().to_string();
+
+    let questionnaire = Questionnaire::load();
+    for (field_id, value) in [(FIELD_CHARACTER_NAME, &character_name), (FIELD_REALM_NAME, &realm_name)] {
+        if let Some(field) = questionnaire.get_field(field_id)
+            && let Err(reason) = field.validate(guild_id, chapter.as_ref().map(|chapter| chapter.flavour), value, &interaction.locale) {
+            interaction.create_response(
+                &ctx.http,
+                CreateInteractionResponse::Message(
+                    CreateInteractionResponseMessage::new()
+                        .content(reason)
+                        .ephemeral(true)
+                ),
+            ).await?;
+            return Ok(());
+        }
+    }
+
+    let mut card = *interaction.message.clone().ok_or(serenity::Error::Other("No card message found"))?;
+    let record = MemberJoinMessage::get_message_by_message_id(card.id.to_string()).await
+        .map_err(|_| serenity::Error::Other("No onboarding record found for this card"))?;
+
+    let flavour = record.flavour.or(chapter.as_ref().map(|chapter| chapter.flavour)).unwrap_or_default();
+    let result = record.update_details(character_name.clone(), realm_name.clone(), record.chapter_name.clone(), flavour, record.faction).await;
+    if let Err(e) = result {
+        println!("Error storing changed character in database: {}", e);
+        return Err(serenity::Error::Other("Could not store the changed character"));
+    }
+
+    let member = guild_id.member(&ctx.http, UserId::new(record.discord_user_id)).await?;
+    apply_nickname_policy(ctx, &member, &character_name, &realm_name, &record.chapter_name).await;
+
+    if let Some(embed) = card.embeds.first() {
+        let edited_embed = replace_card_fields(embed, &[
+            ("Character Name", character_name.clone()),
+            ("Realm", realm_name.clone()),
+        ]);
+        card.edit(&ctx.http, EditMessage::new().embeds(vec![edited_embed])).await?;
+    }
+
+    interaction.create_response(
+        &ctx.http,
+        CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new()
+                .content(templates.text("character_changed", &[
+                    ("member", format!("<@{}>", record.discord_user_id)),
+                    ("character", character_name),
+                    ("realm", realm_name),
+                ]))
+                .ephemeral(true)
+        ),
+    ).await?;
+
+    Ok(())
+}
+
+/// Moves the member on a card to another chapter: swaps their chapter, flavour and faction roles, updates
+/// their record, nickname and the card, and lets the new chapter's officers know.
+async fn move_card_member(ctx: &client::Context, guild_id: GuildId, card: &mut Message, record: &MemberJoinMessage, new_chapter: &Chapter) -> Result<(), serenity::Error> {
+    let chapters = Chapters::for_guild(guild_id);
+    let member = guild_id.member(&ctx.http, UserId::new(record.discord_user_id)).await?;
+
+    let config = config::Config::for_guild(guild_id);
+    if let Some(old_chapter) = chapters.get_by_name(&record.chapter_name) {
+        member.remove_role(&ctx.http, RoleId::new(old_chapter.role_id)).await?;
+        if old_chapter.flavour != new_chapter.flavour && let Some(flavour_role_id) = config.flavour_role_id(old_chapter.flavour) {
+            member.remove_role(&ctx.http, RoleId::new(flavour_role_id)).await?;
+        }
+        if let Some(faction_role_id) = old_chapter.faction_role_id(record.faction) {
+            member.remove_role(&ctx.http, RoleId::new(faction_role_id)).await?;
+        }
+    }
+    member.add_role(&ctx.http, RoleId::new(new_chapter.role_id)).await?;
+    if let Some(flavour_role_id) = config.flavour_role_id(new_chapter.flavour) {
+        member.add_role(&ctx.http, RoleId::new(flavour_role_id)).await?;
+    }
+    // The member keeps their faction when moving between split chapters. Moving into a split chapter
+    // without one leaves the faction role for officers to hand out.
+    if let Some(faction_role_id) = new_chapter.faction_role_id(record.faction) {
+        member.add_role(&ctx.http, RoleId::new(faction_role_id)).await?;
+    }
+
+    let result = record.update_details(record.character_name.clone(), record.realm_name.clone(), new_chapter.name.clone(), new_chapter.flavour, record.faction).await;
+    if let Err(e) = result {
+        println!("Error updating chapter in database: {}", e);
+    }
+
+    apply_nickname_policy(ctx, &member, &record.character_name, &record.realm_name, &new_chapter.name).await;
+    waitlist::notify_open_slots(ctx, guild_id, &record.chapter_name).await;
+
+    if let Some(embed) = card.embeds.first() {
+        let edited_embed = replace_card_fields(embed, &[
+            ("Chapter", new_chapter.name.clone()),
+            ("Game", Templates::for_guild(guild_id).text(&new_chapter.flavour.label_key(), &[])),
+        ]);
+        card.edit(&ctx.http, EditMessage::new().content(officer_mention(new_chapter)).embeds(vec![edited_embed])).await?;
+    }
+    notify_chapter_officers(ctx, guild_id, card, new_chapter, record.discord_user_id).await?;
+
+    Ok(())
+}
+
+pub async fn handle_reject_registration(ctx: &client::Context, interaction: ComponentInteraction) -> Result<(), serenity::Error> {
+    let guild_id = interaction.guild_id.ok_or(serenity::Error::Other("No guild ID found"))?;
+    let chapter = card_chapter(guild_id, &interaction.message).await;
+    if !authorize_component(ctx, &interaction, Action::Reject, chapter.as_ref()).await? {
+        return Ok(());
+    }
+
+    let templates = Templates::for_guild(guild_id);
+
+    let record = MemberJoinMessage::get_message_by_message_id(interaction.message.id.to_string()).await
+        .map_err(|_| serenity::Error::Other("No onboarding record found for this card"))?;
+
+    let member = guild_id.member(&ctx.http, UserId::new(record.discord_user_id)).await?;
+    restore_new_member_roles(ctx, guild_id, &member, &record).await?;
+
+    let result = record.update_message(MemberJoinMessageStage::Rejected).await;
+    if let Err(e) = result {
+        println!("Error updating message in database: {}", e);
+    }
+    waitlist::notify_open_slots(ctx, guild_id, &record.chapter_name).await;
+
+    let vars = [
+        ("member", format!("<@{}>", record.discord_user_id)),
+        ("officer", format!("<@{}>", interaction.user.id)),
+    ];
+    let mut card = *interaction.message.clone();
+    let Some(embed) = card.embeds.first() else {
+        return Err(serenity::Error::Other("Card has no embed"));
+    };
+    let edited_embed = replace_card_field(embed, "Status", templates.text("status_rejected", &vars))
+        .timestamp(Timestamp::now());
+    card.edit(&ctx.http, EditMessage::new().embeds(vec![edited_embed]).components(vec![])).await?;
+
+    interaction.create_response(
+        &ctx.http,
+        CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new()
+                .content(Templates::for_locale(&interaction.locale).text("member_rejected", &vars))
+                .ephemeral(true)
+        ),
+    ).await?;
+
+    Ok(())
+}
+
+/// Decides a member's `/me chapter` request from the buttons on their card.
+pub async fn handle_chapter_request(ctx: &client::Context, interaction: ComponentInteraction) -> Result<(), serenity::Error> {
+    let guild_id = interaction.guild_id.ok_or(serenity::Error::Other("No guild ID found"))?;
+    let chapter = card_chapter(guild_id, &interaction.message).await;
+    if !authorize_component(ctx, &interaction, Action::ChangeChapter, chapter.as_ref()).await? {
+        return Ok(());
+    }
+
+    let templates = Templates::for_locale(&interaction.locale);
+    let record = MemberJoinMessage::get_message_by_message_id(interaction.message.id.to_string()).await
+        .map_err(|_| serenity::Error::Other("No onboarding record found for this card"))?;
+    let approved = interaction.data.custom_id == "button_approve_chapter_request";
+    let chapters = Chapters::for_guild(guild_id);
+    let requested_chapter = record.requested_chapter.as_deref()
+        .and_then(|name| chapters.find_id_by_name(name))
+        .and_then(|id| chapters.get_active_by_id(id))
+        .cloned();
+
+    let mut card = *interaction.message.clone();
+    let vars = [
+        ("member", format!("<@{}>", record.discord_user_id)),
+        ("chapter", record.requested_chapter.clone().unwrap_or_default()),
+        ("officer", format!("<@{}>", interaction.user.id)),
+    ];
+    // The chapter may have filled up or closed since the member asked.
+    if approved && let Some(new_chapter) = &requested_chapter && waitlist::free_slots(guild_id, new_chapter).await == 0 {
+        interaction.create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(templates.text("chapter_request_full", &vars))
+                    .ephemeral(true)
+            ),
+        ).await?;
+        return Ok(());
+    }
+
+    let reply = match (approved, requested_chapter) {
+        (true, Some(new_chapter)) => {
+            move_card_member(ctx, guild_id, &mut card, &record, &new_chapter).await?;
+            templates.text("chapter_request_approved", &vars)
+        }
+        (true, None) => templates.text("chapter_request_unavailable", &vars),
+        (false, _) => templates.text("chapter_request_denied", &vars),
+    };
+
+    if let Err(e) = record.set_requested_chapter(String::new()).await {
+        println!("Error clearing chapter request in database: {}", e);
+    }
+    let Some(embed) = card.embeds.first() else {
+        return Err(serenity::Error::Other("Card has no embed"));
+    };
+    let mut edit = EditMessage::new().embeds(vec![remove_card_field(embed, REQUESTED_CHAPTER_FIELD)]);
+    for button in card_buttons(guild_id, &record.stage, false) {
+        edit = edit.button(button);
+    }
+    card.edit(&ctx.http, edit).await?;
+
+    // The member hears back in the guild's language, as the decision is not made in front of them.
+    let member_text = if approved {
+        Templates::for_guild(guild_id).text("me_chapter_approved", &vars)
+    } else {
+        Templates::for_guild(guild_id).text("me_chapter_denied", &vars)
+    };
+    if let Err(e) = UserId::new(record.discord_user_id).direct_message(&ctx.http, CreateMessage::new().content(member_text)).await {
+        println!("Error telling {} about their chapter request: {}", record.discord_user_id, e);
+    }
+
+    interaction.create_response(
+        &ctx.http,
+        CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new()
+                .content(reply)
+                .ephemeral(true)
+        ),
+    ).await?;
+
+    Ok(())
+}
+
+/// Puts a card changed by its member back in front of officers: updates the character and realm,
+/// resets the status and buttons to awaiting approval and pings the chapter's officers.
+pub async fn reflag_edited_card(ctx: &client::Context, guild_id: GuildId, record: &MemberJoinMessage, character_name: &str, realm_name: &str) -> Result<(), serenity::Error> {
+    let mut card = fetch_card(ctx, guild_id, record).await?;
+    let vars = [("member", format!("<@{}>", record.discord_user_id))];
+    let Some(embed) = card.embeds.first() else {
+        return Err(serenity::Error::Other("Card has no embed"));
+    };
+    let edited_embed = replace_card_fields(embed, &[
+        ("Character Name", character_name.to_string()),
+        ("Realm", realm_name.to_string()),
+        ("Status", Templates::for_guild(guild_id).text("status_edited_by_member", &vars)),
+    ]).timestamp(Timestamp::now());
+
+    let mut edit = EditMessage::new().embeds(vec![edited_embed]);
+    for button in card_buttons(guild_id, &MemberJoinMessageStage::Onboarding, record.requested_chapter.is_some()) {
+        edit = edit.button(button);
+    }
+    card.edit(&ctx.http, edit).await?;
+
+    if let Some(chapter) = card_chapter(guild_id, &card).await {
+        notify_chapter_officers(ctx, guild_id, &card, &chapter, record.discord_user_id).await?;
+    }
+
+    Ok(())
+}
+
+/// Shows a member's chapter change request on their card, with buttons for officers to decide it.
+pub async fn flag_chapter_request(ctx: &client::Context, guild_id: GuildId, record: &MemberJoinMessage, requested_chapter: &Chapter) -> Result<(), serenity::Error> {
+    let mut card = fetch_card(ctx, guild_id, record).await?;
+    let vars = [
+        ("member", format!("<@{}>", record.discord_user_id)),
+        ("chapter", requested_chapter.name.clone()),
+    ];
+    let Some(embed) = card.embeds.first() else {
+        return Err(serenity::Error::Other("Card has no embed"));
+    };
+    let edited_embed = replace_card_field(embed, REQUESTED_CHAPTER_FIELD, Templates::for_guild(guild_id).text("chapter_request_field", &vars));
+
+    let mut edit = EditMessage::new().embeds(vec![edited_embed]);
+    for button in card_buttons(guild_id, &record.stage, true) {
+        edit = edit.button(button);
+    }
+    card.edit(&ctx.http, edit).await?;
+
+    if let Some(chapter) = card_chapter(guild_id, &card).await {
+        notify_chapter_officers(ctx, guild_id, &card, &chapter, record.discord_user_id).await?;
+    }
+
+    Ok(())
+}
+
+/// Puts the member back where they started so they can submit the form again.
+async fn restore_new_member_roles(ctx: &client::Context, guild_id: GuildId, member: &Member, record: &MemberJoinMessage) -> Result<(), serenity::Error> {
+    let config = config::Config::for_guild(guild_id);
+    let chapters = Chapters::for_guild(guild_id);
+
+    member.remove_role(&ctx.http, RoleId::new(config.member_role_id)).await?;
+    if let Some(chapter) = chapters.get_by_name(&record.chapter_name) {
+        member.remove_role(&ctx.http, RoleId::new(chapter.role_id)).await?;
+        if let Some(flavour_role_id) = config.flavour_role_id(chapter.flavour) {
+            member.remove_role(&ctx.http, RoleId::new(flavour_role_id)).await?;
+        }
+        if let Some(faction_role_id) = chapter.faction_role_id(record.faction) {
+            member.remove_role(&ctx.http, RoleId::new(faction_role_id)).await?;
+        }
+    }
+    member.add_role(&ctx.http, RoleId::new(config.new_member_role_id)).await?;
+
+    Ok(())
+}
+
+/// Marks the member's card complete, as its Mark Complete button does.
+pub async fn complete_card(ctx: &client::Context, guild_id: GuildId, record: &MemberJoinMessage, officer_id: UserId) -> Result<(), serenity::Error> {
+    let mut card = fetch_card(ctx, guild_id, record).await?;
+    let vars = [("officer", format!("<@{}>", officer_id))];
+    let Some(embed) = card.embeds.first() else {
+        return Err(serenity::Error::Other("Card has no embed"));
+    };
+    let edited_embed = replace_card_field(embed, "Status", Templates::for_guild(guild_id).text("status_complete", &vars))
+        .timestamp(Timestamp::now());
+
+    let mut edit = EditMessage::new().embeds(vec![edited_embed]);
+    for button in card_buttons(guild_id, &MemberJoinMessageStage::Completed, record.requested_chapter.is_some()) {
+        edit = edit.button(button);
+    }
+    card.edit(&ctx.http, edit).await?;
+
+    let result = record.update_message(MemberJoinMessageStage::Completed).await;
+    if let Err(e) = result {
+        println!("Error updating message in database: {}", e);
+    }
+
+    Ok(())
+}
+
+/// Sends a member back to the very start: takes away the roles the form gave them, forgets
+/// their answers and turns their card back into the card of a fresh join.
+pub async fn reset_onboarding(ctx: &client::Context, guild_id: GuildId, record: &MemberJoinMessage) -> Result<(), serenity::Error> {
+    let member = guild_id.member(&ctx.http, UserId::new(record.discord_user_id)).await?;
+    restore_new_member_roles(ctx, guild_id, &member, record).await?;
+
+    if let Err(e) = MemberAnswer::delete_answers(guild_id.to_string(), record.discord_user_id.to_string()).await {
+        println!("Error deleting answers from database: {}", e);
+    }
+    if let Err(e) = WaitlistEntry::remove_member(guild_id.to_string(), record.discord_user_id.to_string()).await {
+        println!("Error removing member from waitlists: {}", e);
+    }
+    if let Err(e) = record.clear_details().await {
+        println!("Error clearing onboarding details in database: {}", e);
+    }
+    if let Err(e) = record.update_message(MemberJoinMessageStage::NewMember).await {
+        println!("Error updating message in database: {}", e);
+    }
+    waitlist::notify_open_slots(ctx, guild_id, &record.chapter_name).await;
+
+    // Read the record back so the card shows what is stored now.
+    let record = MemberJoinMessage::get_message_by_message_id(record.message_id.to_string()).await
+        .map_err(|_| serenity::Error::Other("No onboarding record found for this card"))?;
+    rebuild_card(ctx, guild_id, &record).await
+}
+
+/// A card drawn from an onboarding record, ready to be posted or written over an existing card.
+pub struct RenderedCard {
+    content: String,
+    embed: CreateEmbed,
+    buttons: Vec<CreateButton>,
+}
+
+impl RenderedCard {
+    /// Replaces everything on the card, removing buttons the record's stage does not have.
+    pub fn into_edit_message(self) -> EditMessage {
+        let mut edit = EditMessage::new()
+            .content(self.content)
+            .embeds(vec![self.embed])
+            .components(vec![]);
+        for button in self.buttons {
+            edit = edit.button(button);
+        }
+
+        edit
+    }
+
+    /// A new message with the card. The officer role is named but not pinged again.
+    pub fn into_create_message(self) -> CreateMessage {
+        let mut message = CreateMessage::new()
+            .content(self.content)
+            .embed(self.embed)
+            .allowed_mentions(CreateAllowedMentions::new());
+        for button in self.buttons {
+            message = message.button(button);
+        }
+
+        message
+    }
+}
+
+/// Draws a member's card from their onboarding record and stored answers alone, ignoring
+/// whatever the card in the channel currently shows.
+pub async fn render_card(guild_id: GuildId, record: &MemberJoinMessage) -> RenderedCard {
+    // Members who have not submitted the form yet have the card they got on joining.
+    if record.chapter_name.is_empty() {
+        let invite = record.invite_code.clone().map(|code| UsedInvite {
+            code,
+            inviter_id: record.invited_by.map(UserId::new),
+        });
+        return RenderedCard {
+            content: String::new(),
+            embed: create_joined_embed(guild_id, record.discord_user_id, invite.as_ref(), record.stage.label_key()),
+            buttons: Vec::new(),
+        };
+    }
+
+    // A chapter since removed from chapters.json is still shown under the name on record.
+    let chapter = Chapters::for_guild(guild_id).get_by_name(&record.chapter_name).cloned()
+        .unwrap_or_else(|| Chapter {
+            name: record.chapter_name.clone(),
+            flavour: record.flavour.unwrap_or_default(),
+            ..Default::default()
+        });
+    let answers = match MemberAnswer::get_answers(guild_id.to_string(), record.discord_user_id.to_string()).await {
+        Ok(answers) => answers,
+        Err(e) => {
+            println!("Error getting answers from database: {}", e);
+            Vec::new()
+        }
+    };
+
+    let mut embed = create_officer_card_embed(guild_id, record.discord_user_id, record.character_name.clone(), record.realm_name.clone(), &chapter, record.faction, record.stage.label_key())
+        .fields(extra_answer_fields(guild_id, &answers).into_iter().map(|(name, value)| (name, value, false)));
+    if let Some(requested_chapter) = &record.requested_chapter {
+        let vars = [
+            ("member", format!("<@{}>", record.discord_user_id)),
+            ("chapter", requested_chapter.clone()),
+        ];
+        embed = embed.field(REQUESTED_CHAPTER_FIELD, Templates::for_guild(guild_id).text("chapter_request_field", &vars), true);
+    }
+
+    let buttons = match record.stage {
+        MemberJoinMessageStage::Onboarding | MemberJoinMessageStage::Completed => card_buttons(guild_id, &record.stage, record.requested_chapter.is_some()),
+        _ => Vec::new(),
+    };
+
+    RenderedCard {
+        content: officer_mention(&chapter),
+        embed,
+        buttons,
+    }
+}
+
+/// Posts the member's card again from their record and points the record at it. The old card
+/// is deleted if it is still there, so only one card has working buttons.
+pub async fn repost_card(ctx: &client::Context, guild_id: GuildId, record: &MemberJoinMessage) -> Result<Message, serenity::Error> {
+    let channel_id = ChannelId::new(config::Config::for_guild(guild_id).nmi_channel_id);
+    let card = channel_id.send_message(&ctx.http, render_card(guild_id, record).await.into_create_message()).await?;
+    if let Err(e) = record.set_message_id(card.id.to_string()).await {
+        println!("Error storing reposted card in database: {}", e);
+        card.delete(&ctx.http).await?;
+        return Err(serenity::Error::Other("Could not store the reposted card"));
+    }
+
+    if let Err(e) = channel_id.delete_message(&ctx.http, MessageId::new(record.message_id)).await
+        && !is_deleted(&e) {
+        println!("Error deleting replaced card: {}", e);
+    }
+
+    Ok(card)
+}
+
+/// Re-posts the cards of members still waiting on onboarding whose card was deleted, e.g. while
+/// the bot was offline. Returns the number of cards re-posted.
+pub async fn reconcile_cards(ctx: &client::Context, guild_id: GuildId) -> Result<usize, serenity::Error> {
+    let records = MemberJoinMessage::get_all(guild_id.to_string()).await
+        .map_err(|_| serenity::Error::Other("Could not load onboarding records"))?;
+    let channel_id = ChannelId::new(config::Config::for_guild(guild_id).nmi_channel_id);
+
+    let mut reposted = 0;
+    // Finished cards are left alone, as checking every one would take a request per member ever onboarded.
+    let open_records = MemberJoinMessage::latest_per_member(records).into_iter()
+        .filter(|record| matches!(record.stage, MemberJoinMessageStage::NewMember | MemberJoinMessageStage::Onboarding));
+    for record in open_records {
+        match ctx.http.get_message(channel_id, MessageId::new(record.message_id)).await {
+            Ok(_) => {}
+            Err(e) if is_deleted(&e) => {
+                // Members who left while the bot was offline don't need a card.
+                if guild_id.member(&ctx.http, UserId::new(record.discord_user_id)).await.is_err() {
+                    continue;
+                }
+                // One card that cannot be posted must not keep the rest from being checked.
+                match repost_card(ctx, guild_id, &record).await {
+                    Ok(_) => reposted += 1,
+                    Err(e) => println!("Error re-posting card of {}: {}", record.discord_user_id, e),
+                }
+            }
+            Err(e) => {
+                println!("Error checking card of {}: {}", record.discord_user_id, e);
+            }
+        }
+    }
+
+    Ok(reposted)
+}
+
+/// Whether Discord answered that the message is gone, as opposed to the request failing.
+fn is_deleted(error: &serenity::Error) -> bool {
+    matches!(error, serenity::Error::Http(http_error) if http_error.status_code().is_some_and(|status| status.as_u16() == 404))
+}
+
+/// Writes the card drawn from the member's record over their card in the NMI channel.
+pub async fn rebuild_card(ctx: &client::Context, guild_id: GuildId, record: &MemberJoinMessage) -> Result<(), serenity::Error> {
+    let mut card = fetch_card(ctx, guild_id, record).await?;
+    card.edit(&ctx.http, render_card(guild_id, record).await.into_edit_message()).await?;
+
+    Ok(())
+}
+
+async fn fetch_card(ctx: &client::Context, guild_id: GuildId, record: &MemberJoinMessage) -> Result<Message, serenity::Error> {
+    let channel_id = ChannelId::new(config::Config::for_guild(guild_id).nmi_channel_id);
+    ctx.http.get_message(channel_id, MessageId::new(record.message_id)).await
+}
 
-    let button_change_chapter = CreateButton::new("button_change_chapter")
-        .style(ButtonStyle::Secondary)
-        .label("Change Chapter");
+/// The buttons a card shows at a stage, plus the chapter request buttons while a request is open.
+fn card_buttons(guild_id: GuildId, stage: &MemberJoinMessageStage, has_chapter_request: bool) -> Vec<CreateButton> {
+    let mut buttons = match stage {
+        MemberJoinMessageStage::Completed => create_completed_onboarding_buttons(guild_id),
+        _ => create_new_member_buttons(guild_id),
+    };
+    if has_chapter_request {
+        let template = Templates::for_guild(guild_id).message("officer_card");
+        buttons.push(template.button("button_approve_chapter_request", ButtonStyle::Success, &[]));
+        buttons.push(template.button("button_deny_chapter_request", ButtonStyle::Secondary, &[]));
+    }
+
+    buttons
+}
+
+/// Chapter a card belongs to, from its onboarding record or failing that the card's Chapter field.
+async fn card_chapter(guild_id: GuildId, card: &Message) -> Option<Chapter> {
+    let chapter_name = match MemberJoinMessage::get_message_by_message_id(card.id.to_string()).await {
+        Ok(record) if !record.chapter_name.is_empty() => record.chapter_name,
+        _ => card.embeds.first()?.fields.iter().find(|field| field.name == "Chapter")?.value.clone(),
+    };
+
+    Chapters::for_guild(guild_id).get_by_name(&chapter_name).cloned()
+}
+
+fn officer_mention(chapter: &Chapter) -> String {
+    chapter.officer_role_id.map(|role_id| format!("<@&{}>", role_id)).unwrap_or_default()
+}
+
+/// Pings the chapter's officer role under the card. Mentions added by editing a message do not notify anyone.
+async fn notify_chapter_officers(ctx: &client::Context, guild_id: GuildId, card: &Message, chapter: &Chapter, discord_user_id: u64) -> Result<(), serenity::Error> {
+    let Some(officer_role_id) = chapter.officer_role_id else {
+        return Ok(());
+    };
+
+    let text = Templates::for_guild(guild_id).text("officer_review_requested", &[
+        ("officer_role", officer_mention(chapter)),
+        ("member", format!("<@{}>", discord_user_id)),
+        ("chapter", chapter.name.clone()),
+    ]);
+    let message = CreateMessage::new()
+        .content(text)
+        .reference_message(card)
+        .allowed_mentions(CreateAllowedMentions::new().roles(vec![RoleId::new(officer_role_id)]));
+    card.channel_id.send_message(&ctx.http, message).await?;
+
+    Ok(())
+}
+
+/// Rebuilds a card embed with one field's value replaced, appending the field if the card predates it.
+fn replace_card_field(embed: &Embed, name: &str, value: String) -> CreateEmbed {
+    replace_card_fields(embed, &[(name, value)])
+}
+
+/// Rebuilds a card embed without the named field.
+fn remove_card_field(embed: &Embed, name: &str) -> CreateEmbed {
+    let mut embed = embed.clone();
+    embed.fields.retain(|field| field.name != name);
+    replace_card_fields(&embed, &[])
+}
+
+/// Like `replace_card_field`, for several fields at once.
+fn replace_card_fields(embed: &Embed, replacements: &[(&str, String)]) -> CreateEmbed {
+    // Everything but the fields is copied as is, so the template's look survives the edit.
+    let mut edited_embed = CreateEmbed::new();
+    if let Some(author) = &embed.author {
+        edited_embed = edited_embed.author(CreateEmbedAuthor::from(author.clone()));
+    }
+    if let Some(title) = &embed.title {
+        edited_embed = edited_embed.title(title.clone());
+    }
+    if let Some(url) = &embed.url {
+        edited_embed = edited_embed.url(url.clone());
+    }
+    if let Some(description) = &embed.description {
+        edited_embed = edited_embed.description(description.clone());
+    }
+    if let Some(colour) = embed.colour {
+        edited_embed = edited_embed.colour(colour);
+    }
+    if let Some(footer) = &embed.footer {
+        edited_embed = edited_embed.footer(CreateEmbedFooter::from(footer.clone()));
+    }
+    if let Some(thumbnail) = &embed.thumbnail {
+        edited_embed = edited_embed.thumbnail(thumbnail.url.clone());
+    }
+    if let Some(image) = &embed.image {
+        edited_embed = edited_embed.image(image.url.clone());
+    }
+    if let Some(timestamp) = embed.timestamp {
+        edited_embed = edited_embed.timestamp(timestamp);
+    }
+
+    for field in &embed.fields {
+        match replacements.iter().find(|(name, _)| field.name == *name) {
+            Some((name, value)) => edited_embed = edited_embed.field(*name, value.clone(), field.inline),
+            None => edited_embed = edited_embed.field(field.name.clone(), field.value.clone(), field.inline),
+        }
+    }
+    for (name, value) in replacements {
+        if !embed.fields.iter().any(|field| field.name == *name) {
+            edited_embed = edited_embed.field(*name, value.clone(), true);
+        }
+    }
 
-    vec![button_complete_registration, button_change_chapter]
+    edited_embed
 }
@@ -1,74 +1,150 @@
-use serenity::all::{ButtonStyle, CommandInteraction, CreateCommand, CreateInteractionResponseMessage, CreateMessage, Member, MessageBuilder, Permissions, ResolvedOption};
+use serenity::all::{ButtonStyle, ChannelId, CommandInteraction, CommandOptionType, CreateButton, CreateCommand, CreateCommandOption, CreateEmbed, CreateInteractionResponseMessage, CreateMessage, EditMessage, GuildId, Member, MessageBuilder, MessageId, Permissions, ResolvedOption};
 use serenity::async_trait;
 use serenity::model::channel::Message;
 use serenity::model::gateway::Ready;
 use serenity::builder;
 use serenity::builder::CreateInteractionResponse;
-use serenity::model::colour;
 use serenity::prelude::*;
-use crate::secrets;
+use crate::permissions::{authorize_command, Action};
+use crate::config;
 use crate::chapters::Chapters;
+use crate::member_db::WelcomeMessage;
+use crate::templates::Templates;
 
-pub async fn send_welcome_message(ctx: Context, command: CommandInteraction) {
-    let member = command.clone().member.expect("Could not get member.");
-    let secrets = secrets::Secrets::get_secrets();
-    if secrets.authorized_ids.contains(&member.user.id.get()) == false {
-        let message = command.clone().create_response(
-            &ctx,
-            CreateInteractionResponse::Message(
-                CreateInteractionResponseMessage::default()
-                    .ephemeral(true)
-                    .content("You are not authorized to use this command.")
-            ))
-            .await
-            .expect("Could not send denial response.");
-        return;
+pub async fn send_welcome_message(ctx: Context, command: CommandInteraction) -> Result<(), serenity::Error> {
+    let member = command.clone().member.ok_or(serenity::Error::Other("Could not get member."))?;
+    let templates = Templates::for_locale(&command.locale);
+    let authorized = authorize_command(&ctx, &command, Action::Admin).await?;
+    if !authorized {
+        return Ok(());
     }
 
     command.create_response(
         &ctx,
         CreateInteractionResponse::Message(CreateInteractionResponseMessage::new().ephemeral(true).content(
-            "Welcome message sending in progress..",
+            templates.text("welcome_sending", &[]),
         ))
-    ).await.expect("Could not acknowledge command");
+    ).await?;
 
-    let message = create_chapter_message();
-    let result = command.channel_id.send_message(ctx.http, message).await;
+    let guild_id = config::Config::guild_or_primary(command.guild_id);
+    let message = create_chapter_message(guild_id);
+    let result = command.channel_id.send_message(&ctx.http, message).await;
     match result {
-        Ok(_) => println!("Sent welcome message to {}", member.user.name),
+        Ok(sent) => {
+            println!("Sent welcome message to {}", member.user.name);
+            replace_tracked_welcome_messages(&ctx, guild_id, &sent).await;
+        }
         Err(why) => eprintln!("Error sending welcome message: {:?}", why),
     }
+
+    Ok(())
+}
+
+/// Removes the guild's previously posted welcome messages and tracks the new one in their place.
+async fn replace_tracked_welcome_messages(ctx: &Context, guild_id: GuildId, sent: &Message) {
+    match WelcomeMessage::get_all().await {
+        Ok(previous_messages) => {
+            for previous in previous_messages.into_iter().filter(|previous| previous.guild_id == guild_id.get()) {
+                let result = ChannelId::new(previous.channel_id).delete_message(&ctx.http, MessageId::new(previous.message_id)).await;
+                if let Err(e) = result && !is_not_found(&e) {
+                    println!("Error deleting old welcome message: {}", e);
+                    continue;
+                }
+                if let Err(e) = previous.delete().await {
+                    println!("Error removing old welcome message from database: {}", e);
+                }
+            }
+        }
+        Err(e) => {
+            println!("Error getting welcome messages from database: {}", e);
+        }
+    }
+
+    let result = WelcomeMessage::push_message(guild_id.to_string(), sent.channel_id.to_string(), sent.id.to_string()).await;
+    if let Err(e) = result {
+        println!("Error pushing welcome message to database: {}", e);
+    }
+}
+
+/// Edits every tracked welcome message in place so it shows its guild's current chapter list.
+/// Copies that no longer exist are dropped from tracking. Returns the number updated.
+pub async fn refresh_welcome_messages(ctx: &Context) -> Result<usize, serenity::Error> {
+    let tracked = WelcomeMessage::get_all().await
+        .map_err(|_| serenity::Error::Other("Could not load welcome messages"))?;
+
+    let mut updated = 0;
+    for welcome_message in tracked {
+        let guild_id = GuildId::new(welcome_message.guild_id);
+        let edit = EditMessage::new()
+            .embed(create_chapter_embed(guild_id))
+            .button(create_nmi_button(guild_id));
+        let result = ChannelId::new(welcome_message.channel_id)
+            .edit_message(&ctx.http, MessageId::new(welcome_message.message_id), edit)
+            .await;
+
+        match result {
+            Ok(_) => updated += 1,
+            Err(e) if is_not_found(&e) => {
+                if let Err(e) = welcome_message.delete().await {
+                    println!("Error removing missing welcome message from database: {}", e);
+                }
+            }
+            Err(e) => {
+                println!("Error refreshing welcome message: {}", e);
+            }
+        }
+    }
+
+    Ok(updated)
+}
+
+pub(crate) fn is_not_found(error: &serenity::Error) -> bool {
+    matches!(error, serenity::Error::Http(http_error) if http_error.status_code().map(|code| code.as_u16()) == Some(404))
 }
 
 pub async fn register_welcome_message_command() -> CreateCommand {
     CreateCommand::new("create_welcome_message").description("Ads the NMI Welcome Message.")
 }
 
-fn create_chapter_message() -> CreateMessage {
-    let chapters = Chapters::load();
-    let chapter_list = chapters.to_formatted_list();
+pub async fn register_welcome_command() -> CreateCommand {
+    let refresh = CreateCommandOption::new(CommandOptionType::SubCommand, "refresh", "Update posted welcome messages with the current chapter list.");
+
+    CreateCommand::new("welcome").description("Manage the NMI welcome message.")
+        .add_option(refresh)
+}
+
+pub async fn handle_welcome_command(ctx: &Context, command: &CommandInteraction) -> Result<(), serenity::Error> {
+    let templates = Templates::for_locale(&command.locale);
+    if !authorize_command(ctx, command, Action::Admin).await? {
+        return Ok(());
+    }
+
+    let updated = refresh_welcome_messages(ctx).await?;
 
-    let mut body = chapter_list;
-    body += "\n\n";
-    body +="Welcome to the Old Gods! Please find your chapter number above and fill in the form below!";
+    command.create_response(
+        &ctx.http,
+        CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::default()
+                .ephemeral(true)
+                .content(templates.text("welcome_refreshed", &[("count", updated.to_string())]))
+        ),
+    ).await?;
 
-    let nmi_button = builder::CreateButton::new("nmi_button")
-        .label("Chapter Form.")
-        .style(ButtonStyle::Primary);
+    Ok(())
+}
 
-    let guest_button = builder::CreateButton::new("guest_button")
-        .label("I'm a Guest!")
-        .style(ButtonStyle::Secondary);
+fn create_chapter_message(guild_id: GuildId) -> CreateMessage {
+    builder::CreateMessage::new()
+        .embed(create_chapter_embed(guild_id))
+        .button(create_nmi_button(guild_id))
+}
 
-    let embed = builder::CreateEmbed::default()
-        .color(colour::Color::from_rgb(167, 36, 255))
-        .title("New Member Info")
-        .description(body);
+fn create_nmi_button(guild_id: GuildId) -> CreateButton {
+    Templates::for_guild(guild_id).message("welcome").button("nmi_button", ButtonStyle::Primary, &[])
+}
 
-    let message = builder::CreateMessage::new()
-        .embed(embed)
-        .button(nmi_button);
-        //button(guest_button);
+fn create_chapter_embed(guild_id: GuildId) -> CreateEmbed {
+    let chapters = Chapters::for_guild(guild_id);
 
-    message
-}
\ No newline at end of file
+    Templates::for_guild(guild_id).message("welcome").embed(&[("chapter_list", chapters.to_formatted_list())])
+}
@@ -0,0 +1,122 @@
+use serenity::all::{ChannelId, CreateMessage, EditMember, Member};
+use serenity::client;
+use crate::config;
+use crate::templates::Templates;
+
+// Discord rejects nicknames longer than this.
+const MAX_NICKNAME_LENGTH: usize = 32;
+
+enum NicknameOutcome {
+    Disabled,
+    Applied,
+    Skipped(String),
+}
+
+fn render_nickname(template: &str, character_name: &str, realm_name: &str, chapter_name: &str) -> String {
+    let nickname = template
+        .replace("{character}", character_name)
+        .replace("{realm}", realm_name)
+        .replace("{chapter}", chapter_name);
+
+    nickname.chars().take(MAX_NICKNAME_LENGTH).collect()
+}
+
+/// Applies the configured nickname template to a member.
+/// Members the bot cannot manage (the owner, or anyone at or above the bot's highest role) are skipped.
+async fn sync_nickname(ctx: &client::Context, member: &Member, character_name: &str, realm_name: &str, chapter_name: &str) -> Result<NicknameOutcome, serenity::Error> {
+    let config = config::Config::for_guild(member.guild_id);
+    let template = match config.nickname_template {
+        Some(template) if !template.is_empty() => template,
+        _ => return Ok(NicknameOutcome::Disabled),
+    };
+
+    let nickname = render_nickname(&template, character_name, realm_name, chapter_name);
+    if member.nick.as_deref() == Some(nickname.as_str()) {
+        return Ok(NicknameOutcome::Applied);
+    }
+
+    let guild = member.guild_id.to_partial_guild(&ctx.http).await?;
+    if guild.owner_id == member.user.id {
+        return Ok(NicknameOutcome::Skipped("member is the server owner".to_string()));
+    }
+
+    let bot_id = ctx.cache.current_user().id;
+    let bot_member = member.guild_id.member(&ctx.http, bot_id).await?;
+
+    let highest_position = |roles: &[serenity::all::RoleId]| {
+        roles.iter()
+            .filter_map(|role_id| guild.roles.get(role_id))
+            .map(|role| role.position)
+            .max()
+            .unwrap_or(0)
+    };
+
+    if highest_position(&member.roles) >= highest_position(&bot_member.roles) {
+        return Ok(NicknameOutcome::Skipped("member's highest role is not below the bot's".to_string()));
+    }
+
+    member.guild_id.edit_member(&ctx.http, member.user.id, EditMember::new().nickname(nickname)).await?;
+
+    Ok(NicknameOutcome::Applied)
+}
+
+/// Syncs the member's nickname and reports skipped members to the NMI channel.
+/// Failures are logged rather than returned so they never interrupt onboarding.
+pub async fn apply_nickname_policy(ctx: &client::Context, member: &Member, character_name: &str, realm_name: &str, chapter_name: &str) {
+    let result = sync_nickname(ctx, member, character_name, realm_name, chapter_name).await;
+    match result {
+        Ok(outcome) => {
+            if let Err(e) = report_nickname_outcome(ctx, member, &outcome).await {
+                println!("Error reporting nickname sync: {}", e);
+            }
+        }
+        Err(e) => {
+            println!("Error syncing nickname: {}", e);
+        }
+    }
+}
+
+/// Lets officers know a nickname has to be set by hand.
+async fn report_nickname_outcome(ctx: &client::Context, member: &Member, outcome: &NicknameOutcome) -> Result<(), serenity::Error> {
+    if let NicknameOutcome::Skipped(reason) = outcome {
+        println!("Skipped nickname sync for {}: {}", member.user.id, reason);
+
+        let config = config::Config::for_guild(member.guild_id);
+        let vars = [("member", format!("<@{}>", member.user.id)), ("reason", reason.clone())];
+        let message = CreateMessage::new()
+            .content(Templates::for_guild(member.guild_id).text("nickname_skipped", &vars));
+        ChannelId::new(config.nmi_channel_id).send_message(&ctx.http, message).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_nickname_fills_in_placeholders() {
+        let nickname = render_nickname("{character}-{realm} ({chapter})", "Bjork", "Tichondrius", "Aegwynn");
+        assert_eq!(nickname, "Bjork-Tichondrius (Aegwynn)");
+    }
+
+    #[test]
+    fn render_nickname_leaves_unknown_placeholders() {
+        assert_eq!(render_nickname("{character} {guild}", "Bjork", "Tichondrius", "Aegwynn"), "Bjork {guild}");
+    }
+
+    #[test]
+    fn render_nickname_truncates_to_discord_limit() {
+        let nickname = render_nickname("{character} of {realm} in {chapter}", "Bjork", "Tichondrius", "Aegwynn Chapter");
+        assert_eq!(nickname.chars().count(), MAX_NICKNAME_LENGTH);
+        assert_eq!(nickname, "Bjork of Tichondrius in Aegwynn ");
+    }
+
+    #[test]
+    fn render_nickname_counts_characters_not_bytes() {
+        let nickname = render_nickname("{character}", &"é".repeat(40), "", "");
+        assert_eq!(nickname.chars().count(), MAX_NICKNAME_LENGTH);
+        assert!(nickname.len() > MAX_NICKNAME_LENGTH);
+    }
+}
@@ -0,0 +1,152 @@
+use serenity::all::{CommandInteraction, CommandOptionType, CreateAttachment, CreateCommand, CreateCommandOption, ResolvedValue};
+use serenity::builder::{CreateInteractionResponse, CreateInteractionResponseMessage};
+use serenity::client::Context;
+use crate::chapters::Chapters;
+use crate::config::Config;
+use crate::lookup;
+use crate::member_db::MemberJoinMessage;
+use crate::member_info::repost_card;
+use crate::permissions::{authorize_command, authorize_command_for_chapter, Action};
+use crate::templates::Templates;
+
+pub async fn register_nmi_command() -> CreateCommand {
+    let export = CreateCommandOption::new(CommandOptionType::SubCommand, "export", "Download every onboarding record as CSV.");
+
+    let lookup = CreateCommandOption::new(CommandOptionType::SubCommand, "lookup", "Find a member's onboarding record.")
+        .add_sub_option(CreateCommandOption::new(CommandOptionType::User, "member", "The member.").required(false))
+        .add_sub_option(CreateCommandOption::new(CommandOptionType::String, "search", "Character, realm or chapter name. Close spellings match too.").required(false));
+
+    let repost = CreateCommandOption::new(CommandOptionType::SubCommand, "repost", "Post a member's card again from their onboarding record.")
+        .add_sub_option(CreateCommandOption::new(CommandOptionType::User, "member", "The member.").required(true));
+
+    CreateCommand::new("nmi").description("New member onboarding tools.")
+        .add_option(export)
+        .add_option(lookup)
+        .add_option(repost)
+}
+
+pub async fn handle_nmi_command(ctx: &Context, command: &CommandInteraction) -> Result<(), serenity::Error> {
+    let subcommand = command.data.options().first().map(|option| option.name).unwrap_or_default();
+
+    match subcommand {
+        "export" => handle_export(ctx, command).await,
+        "lookup" => lookup::handle_lookup(ctx, command).await,
+        "repost" => handle_repost(ctx, command).await,
+        _ => Ok(()),
+    }
+}
+
+/// Replaces a member's card, e.g. after it was deleted, with one drawn from their record.
+async fn handle_repost(ctx: &Context, command: &CommandInteraction) -> Result<(), serenity::Error> {
+    let guild_id = Config::guild_or_primary(command.guild_id);
+    let templates = Templates::for_locale(&command.locale);
+    let mut user_id = None;
+    for option in command.data.options() {
+        if let ResolvedValue::SubCommand(sub_options) = option.value {
+            for sub_option in sub_options {
+                if let ResolvedValue::User(user, _) = sub_option.value {
+                    user_id = Some(user.id);
+                }
+            }
+        }
+    }
+    let user_id = user_id.ok_or(serenity::Error::Other("No member given"))?;
+    let member = format!("<@{}>", user_id);
+
+    let Ok(record) = MemberJoinMessage::get_message_by_discord_user_id(guild_id.to_string(), user_id.to_string()).await else {
+        command.create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::default()
+                    .ephemeral(true)
+                    .content(templates.text("lookup_no_record", &[("member", member)]))
+            ),
+        ).await?;
+        return Ok(());
+    };
+    let chapter = Chapters::for_guild(guild_id).get_by_name(&record.chapter_name).cloned();
+    if !authorize_command_for_chapter(ctx, command, Action::Complete, chapter.as_ref()).await? {
+        return Ok(());
+    }
+
+    let card = repost_card(ctx, guild_id, &record).await?;
+    println!("{} reposted the card of {}", command.user.id, user_id);
+
+    command.create_response(
+        &ctx.http,
+        CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::default()
+                .ephemeral(true)
+                .content(templates.text("card_reposted", &[("member", member), ("card", card.link())]))
+        ),
+    ).await?;
+
+    Ok(())
+}
+
+async fn handle_export(ctx: &Context, command: &CommandInteraction) -> Result<(), serenity::Error> {
+    if !authorize_command(ctx, command, Action::Export).await? {
+        return Ok(());
+    }
+
+    let templates = Templates::for_locale(&command.locale);
+
+    let guild_id = Config::guild_or_primary(command.guild_id);
+    let records = match MemberJoinMessage::get_all(guild_id.to_string()).await {
+        Ok(records) => records,
+        Err(e) => {
+            println!("Error reading onboarding records from database: {}", e);
+            Vec::new()
+        }
+    };
+
+    if records.is_empty() {
+        command.create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::default()
+                    .ephemeral(true)
+                    .content(templates.text("export_empty", &[]))
+            ),
+        ).await?;
+        return Ok(());
+    }
+
+    let mut csv = String::from("discord_user_id,message_id,stage,character_name,realm_name,chapter_name,flavour,faction,invite_code,invited_by\n");
+    for record in &records {
+        let row = [
+            record.discord_user_id.to_string(),
+            record.message_id.to_string(),
+            format!("{:?}", record.stage),
+            record.character_name.clone(),
+            record.realm_name.clone(),
+            record.chapter_name.clone(),
+            record.flavour.map(|flavour| flavour.key().to_string()).unwrap_or_default(),
+            record.faction.map(|faction| faction.key().to_string()).unwrap_or_default(),
+            record.invite_code.clone().unwrap_or_default(),
+            record.invited_by.map(|user_id| user_id.to_string()).unwrap_or_default(),
+        ];
+        csv += &row.iter().map(|value| csv_escape(value)).collect::<Vec<String>>().join(",");
+        csv += "\n";
+    }
+
+    command.create_response(
+        &ctx.http,
+        CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::default()
+                .ephemeral(true)
+                .content(templates.text("export_ready", &[("count", records.len().to_string())]))
+                .add_file(CreateAttachment::bytes(csv.into_bytes(), "onboarding.csv"))
+        ),
+    ).await?;
+
+    Ok(())
+}
+
+fn csv_escape(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
@@ -1,122 +1,257 @@
-use std::ffi::CString;
-use serenity::all::{ButtonStyle, ChannelId, CommandInteraction, ComponentInteraction, CreateEmbedAuthor, CreateMessage, InputTextStyle, ModalInteraction, Role};
-use serenity::all::Unresolved::RoleId;
-use serenity::builder::{CreateActionRow, CreateInputText, CreateInteractionResponse, CreateModal, CreateInteractionResponseMessage, CreateEmbed, CreateButton};
+use serenity::all::{ButtonStyle, ChannelId, ComponentInteraction, CreateButton, CreateEmbed, CreateMessage, GuildId, ModalInteraction, User, UserId};
+use serenity::builder::{CreateInteractionResponse, CreateInteractionResponseMessage};
 use serenity::client::Context;
-use serenity::futures::{StreamExt, pin_mut};
-use crate::chapters::Chapters;
-use crate::emojis::{emoji_party_popper, emoji_warning};
-use crate::member_info::{create_new_member_buttons, create_new_member_embeds, push_member_completion_message};
-use crate::secrets;
+use crate::chapters::{Chapter, Chapters, Faction, Flavour};
+use crate::invites::UsedInvite;
+use crate::member_db::{MemberAnswer, MemberJoinMessage, WaitlistEntry};
+use crate::member_info::{create_new_member_buttons, create_new_member_embeds, extra_answer_fields, push_member_completion_message};
+use crate::nickname::apply_nickname_policy;
+use crate::rules::{needs_acceptance, show_rules_gate};
+use crate::questionnaire::{Questionnaire, FIELD_CHAPTER_NUMBER, FIELD_CHARACTER_NAME, FIELD_FACTION, FIELD_FLAVOUR, FIELD_REALM_NAME};
+use crate::config;
+use crate::templates::Templates;
+use crate::waitlist;
 
 pub async fn nmi_modal(ctx: &Context, interaction: &ComponentInteraction) -> Result<(), serenity::Error> {
-    let chapter_value = CreateInputText::new(
-        InputTextStyle::Short,
-        "Chapter Number:",
-        "chapter_number"
-    ).required(true).min_length(1).max_length(2).placeholder("0 for Aegwynn, etc...");
-
-    let character_name = CreateInputText::new(
-        InputTextStyle::Short,
-        "Character Name:",
-        "character_name"
-    ).required(true).min_length(2).max_length(14).placeholder("Bjork");
-
-    let realm_name = CreateInputText::new(
-        InputTextStyle::Short,
-        "Realm Name:",
-        "realm_name"
-    ).required(true).min_length(2).max_length(20).placeholder("Tichondrius");
-
-    let modal = CreateInteractionResponse::Modal(
-        CreateModal::new("nmi_modal", "NMI Character Registration")
-            .components(vec![
-                CreateActionRow::InputText(chapter_value),
-                CreateActionRow::InputText(character_name),
-                CreateActionRow::InputText(realm_name)
-            ])
-    );
+    let guild_id = config::Config::guild_or_primary(interaction.guild_id);
+    if needs_acceptance(guild_id, interaction.user.id.get()).await {
+        return show_rules_gate(ctx, interaction, true).await;
+    }
+
+    // Guilds whose chapters all play one game go straight to the form.
+    let chapters = Chapters::for_guild(guild_id);
+    let flavours = chapters.flavours();
+    if flavours.len() > 1 {
+        // A chapter invite already says which game the member plays.
+        let Some(chapter) = invited_chapter(guild_id, interaction.user.id).await.and_then(|id| chapters.get_by_id(id)) else {
+            return show_flavour_picker(ctx, interaction, &flavours).await;
+        };
+        let result = MemberAnswer::save_answer(guild_id.to_string(), interaction.user.id.to_string(), FIELD_FLAVOUR.to_string(), chapter.flavour.key().to_string()).await;
+        if let Err(e) = result {
+            println!("Error storing game flavour in database: {}", e);
+        }
+    }
+
+    show_questionnaire_page(ctx, interaction, 0).await
+}
+
+/// Asks which game the member plays before the form, so they only see that game's chapters.
+async fn show_flavour_picker(ctx: &Context, interaction: &ComponentInteraction, flavours: &[Flavour]) -> Result<(), serenity::Error> {
+    let templates = Templates::for_locale(&interaction.locale);
+
+    let mut response = CreateInteractionResponseMessage::new()
+        .content(templates.text("flavour_pick", &[]))
+        .ephemeral(true);
+    for flavour in flavours {
+        response = response.button(CreateButton::new(format!("nmi_flavour:{}", flavour.key()))
+            .label(templates.text(&flavour.label_key(), &[]))
+            .style(ButtonStyle::Primary));
+    }
+
+    interaction.create_response(&ctx.http, CreateInteractionResponse::Message(response)).await?;
+
+    Ok(())
+}
+
+/// Stores the picked flavour and swaps the picker for that flavour's chapter list and a button into the form.
+pub async fn nmi_flavour(ctx: &Context, interaction: &ComponentInteraction) -> Result<(), serenity::Error> {
+    let guild_id = config::Config::guild_or_primary(interaction.guild_id);
+    let flavour = interaction.data.custom_id.rsplit_once(':')
+        .and_then(|(_, key)| Flavour::from_key(key))
+        .ok_or(serenity::Error::Other("Unknown game flavour"))?;
+
+    let result = MemberAnswer::save_answer(guild_id.to_string(), interaction.user.id.to_string(), FIELD_FLAVOUR.to_string(), flavour.key().to_string()).await;
+    if let Err(e) = result {
+        println!("Error storing game flavour in database: {}", e);
+    }
+
+    let templates = Templates::for_locale(&interaction.locale);
+    let template = templates.message("flavour_chapters");
+    let vars = [
+        ("flavour", templates.text(&flavour.label_key(), &[])),
+        ("chapter_list", Chapters::for_guild(guild_id).to_formatted_list_for(flavour)),
+    ];
+    let open_form = template.button_with_label_key("nmi_continue:0", "nmi_continue", ButtonStyle::Primary, &vars);
+
+    interaction.create_response(
+        &ctx.http,
+        CreateInteractionResponse::UpdateMessage(
+            CreateInteractionResponseMessage::new()
+                .content(template.text(&vars))
+                .button(open_form)
+        ),
+    ).await?;
+
+    Ok(())
+}
+
+/// Opens the next questionnaire page from the "Continue" button between modals.
+pub async fn nmi_continue(ctx: &Context, interaction: &ComponentInteraction) -> Result<(), serenity::Error> {
+    let page_index = custom_id_page(&interaction.data.custom_id);
+    show_questionnaire_page(ctx, interaction, page_index).await
+}
+
+async fn show_questionnaire_page(ctx: &Context, interaction: &ComponentInteraction, page_index: usize) -> Result<(), serenity::Error> {
+    let questionnaire = Questionnaire::load();
+    let page = questionnaire.pages.get(page_index).ok_or(serenity::Error::Other("No questionnaire page found"))?;
+
+    let guild_id = config::Config::guild_or_primary(interaction.guild_id);
+    let prefilled = match invited_chapter(guild_id, interaction.user.id).await {
+        Some(chapter_number) => vec![(FIELD_CHAPTER_NUMBER, chapter_number.to_string())],
+        None => Vec::new(),
+    };
+    let modal = CreateInteractionResponse::Modal(page.to_modal(page_index, &interaction.locale, &prefilled));
 
     interaction.create_response(ctx.http.clone(), modal).await?;
 
     Ok(())
 }
 
+fn custom_id_page(custom_id: &str) -> usize {
+    custom_id.rsplit_once(':')
+        .and_then(|(_, page)| page.parse::<usize>().ok())
+        .unwrap_or(0)
+}
+
 pub async fn nmi_modal_response(ctx: &Context, interaction: &ModalInteraction) -> Result<(), serenity::Error> {
-    interaction.create_response(&ctx.http, CreateInteractionResponse::Message(CreateInteractionResponseMessage::new().content("Please standby...").ephemeral(true))).await?;
-    let default_string = String::new();
-    let chapter_number = &interaction
-        .data
-        .components
-        .get(0)
-        .and_then(|row| row.components.get(0))
-        .and_then(|component| {
-            if let serenity::all::ActionRowComponent::InputText(input) = component {
-                input.value.as_ref()
-            } else {
-                None
-            }
-        })
-        .unwrap_or(&default_string);
+    let questionnaire = Questionnaire::load();
+    let page_index = custom_id_page(&interaction.data.custom_id);
+    let page = questionnaire.pages.get(page_index).ok_or(serenity::Error::Other("No questionnaire page found"))?;
+    let guild_id = interaction.guild_id.ok_or(serenity::Error::Other("No guild ID found"))?;
+    let flavour = picked_flavour(guild_id, interaction.user.id).await;
+
+    let mut answers = Vec::new();
+    for (row, field) in page.fields.iter().enumerate() {
+        let value = modal_input_value(interaction, row).trim().to_string();
+        if let Err(reason) = field.validate(guild_id, flavour, &value, &interaction.locale) {
+            interaction.create_response(
+                ctx.http.clone(),
+                CreateInteractionResponse::Message(
+                    CreateInteractionResponseMessage::default()
+                        .content(reason)
+                        .ephemeral(true)
+                ),
+            ).await?;
+            return Ok(());
+        }
+        answers.push((field.id.clone(), value));
+    }
+
+    for (field_id, value) in answers {
+        let result = MemberAnswer::save_answer(guild_id.to_string(), interaction.user.id.to_string(), field_id, value).await;
+        if let Err(e) = result {
+            println!("Error storing questionnaire answer in database: {}", e);
+        }
+    }
 
-    let _chapter = crate::chapters::Chapters::load();
-    let chapter_number = chapter_number.parse::<u64>().unwrap_or(99);
+    let next_page = page_index + 1;
+    if next_page < questionnaire.page_count() {
+        let template = Templates::for_locale(&interaction.locale).message("questionnaire_continue");
+        let vars = [("page", next_page.to_string()), ("pages", questionnaire.page_count().to_string())];
+        let continue_button = template.button_with_label_key(&format!("nmi_continue:{}", next_page), "nmi_continue", ButtonStyle::Primary, &vars);
 
-    if (chapter_number == 99) || (chapter_number > (_chapter.get_count() as u64 - 1)) {
         interaction.create_response(
-            ctx.http.clone(),
+            &ctx.http,
             CreateInteractionResponse::Message(
-                CreateInteractionResponseMessage::default()
-                    .content("Invalid chapter number. Please try again.")
+                CreateInteractionResponseMessage::new()
+                    .content(template.text(&vars))
+                    .button(continue_button)
                     .ephemeral(true)
             ),
         ).await?;
         return Ok(());
     }
 
-    let default_character_name = String::new();
-    let character_name =
-        &interaction
-            .data
-            .components
-            .get(1)
-            .and_then(|row| row.components.get(0))
-            .and_then(|component| {
-                if let serenity::all::ActionRowComponent::InputText(input) = component {
-                    input.value.as_ref()
-                } else {
-                    None
-                }
-            })
-            .unwrap_or(&default_character_name);
-
-    let default_realm_name = String::new();
-    let realm_name = &interaction
-        .data
-        .components
-        .get(2)
-        .and_then(|row| row.components.get(0))
-        .and_then(|component| {
-            if let serenity::all::ActionRowComponent::InputText(input) = component {
-                input.value.as_ref()
-            } else {
-                None
-            }
-        })
-        .unwrap_or(&default_realm_name);
-    
-    let secrets = secrets::Secrets::get_secrets();
+    let templates = Templates::for_locale(&interaction.locale);
+
+    let answers = MemberAnswer::get_answers(guild_id.to_string(), interaction.user.id.to_string()).await
+        .map_err(|_| serenity::Error::Other("Could not load questionnaire answers"))?;
+    let chapter_number = MemberAnswer::find(&answers, FIELD_CHAPTER_NUMBER).unwrap_or_default().parse::<usize>().unwrap_or(usize::MAX);
+    let chapters = Chapters::for_guild(guild_id);
+    let chapter = chapters.get_active_by_id(chapter_number).ok_or(serenity::Error::Other("No chapter found"))?;
+
+    // Closed and full chapters queue the member instead. Their answers are kept for when they come back.
+    if waitlist::free_slots(guild_id, chapter).await == 0 {
+        let position = waitlist::join(guild_id, interaction.user.id, chapter, &interaction.locale).await?;
+        interaction.create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(templates.text("chapter_waitlisted", &[("chapter", chapter.name.clone()), ("position", position.to_string())]))
+                    .ephemeral(true)
+            ),
+        ).await?;
+        return Ok(());
+    }
+
+    // Split chapters need a faction before the roles can be handed out.
+    if chapter.is_split() {
+        let mut response = CreateInteractionResponseMessage::new()
+            .content(templates.text("faction_pick", &[("chapter", chapter.name.clone())]))
+            .ephemeral(true);
+        for faction in chapter.factions() {
+            response = response.button(CreateButton::new(format!("nmi_faction:{}", faction.key()))
+                .label(templates.text(&faction.label_key(), &[]))
+                .style(ButtonStyle::Primary));
+        }
+        interaction.create_response(&ctx.http, CreateInteractionResponse::Message(response)).await?;
+        return Ok(());
+    }
+
+    interaction.create_response(&ctx.http, CreateInteractionResponse::Message(CreateInteractionResponseMessage::new().content(templates.text("please_standby", &[])).ephemeral(true))).await?;
 
+    finish_onboarding(ctx, guild_id, &interaction.user, &interaction.locale, None).await
+}
+
+/// Stores the faction picked for a split chapter and finishes onboarding with it.
+pub async fn nmi_faction(ctx: &Context, interaction: &ComponentInteraction) -> Result<(), serenity::Error> {
     let guild_id = interaction.guild_id.ok_or(serenity::Error::Other("No guild ID found"))?;
-    let member = guild_id.member(&ctx.http, interaction.user.id).await?;
+    let faction = interaction.data.custom_id.rsplit_once(':')
+        .and_then(|(_, key)| Faction::from_key(key))
+        .ok_or(serenity::Error::Other("Unknown faction"))?;
+
+    let result = MemberAnswer::save_answer(guild_id.to_string(), interaction.user.id.to_string(), FIELD_FACTION.to_string(), faction.key().to_string()).await;
+    if let Err(e) = result {
+        println!("Error storing faction in database: {}", e);
+    }
+
+    let templates = Templates::for_locale(&interaction.locale);
+    interaction.create_response(
+        &ctx.http,
+        CreateInteractionResponse::UpdateMessage(
+            CreateInteractionResponseMessage::new()
+                .content(templates.text("please_standby", &[]))
+                .components(vec![])
+        ),
+    ).await?;
+
+    finish_onboarding(ctx, guild_id, &interaction.user, &interaction.locale, Some(faction)).await
+}
 
-    let new_member_role_id = serenity::model::id::RoleId::new(secrets.new_member_role_id);
+/// Hands out the member's roles from their stored answers, then sends the DM, the chapter welcome and the officer card.
+async fn finish_onboarding(ctx: &Context, guild_id: GuildId, user: &User, locale: &str, faction: Option<Faction>) -> Result<(), serenity::Error> {
+    let templates = Templates::for_locale(locale);
+
+    let answers = MemberAnswer::get_answers(guild_id.to_string(), user.id.to_string()).await
+        .map_err(|_| serenity::Error::Other("Could not load questionnaire answers"))?;
+    let chapter_number = MemberAnswer::find(&answers, FIELD_CHAPTER_NUMBER).unwrap_or_default().parse::<usize>().unwrap_or(usize::MAX);
+    let character_name = MemberAnswer::find(&answers, FIELD_CHARACTER_NAME).unwrap_or_default();
+    let realm_name = MemberAnswer::find(&answers, FIELD_REALM_NAME).unwrap_or_default();
+
+    let _chapter = Chapters::for_guild(guild_id);
+
+    let config = config::Config::for_guild(guild_id);
+
+    let member = guild_id.member(&ctx.http, user.id).await?;
+
+    let new_member_role_id = serenity::model::id::RoleId::new(config.new_member_role_id);
     //let new_member_role = guild_id.role(&ctx.http, new_member_role_id).await?;
 
-    let member_role_id = serenity::model::id::RoleId::new(secrets.member_role_id);
+    let member_role_id = serenity::model::id::RoleId::new(config.member_role_id);
     //let member_role = guild_id.role(&ctx.http, member_role_id).await?;
 
-    let chapter_role_id = serenity::model::id::RoleId::new(_chapter.get_by_id(chapter_number as usize).ok_or(serenity::Error::Other("No chapter found"))?.role_id.clone());
+    let chapter = _chapter.get_by_id(chapter_number).ok_or(serenity::Error::Other("No chapter found"))?;
+    let chapter_role_id = serenity::model::id::RoleId::new(chapter.role_id);
     //let chapter_role = guild_id.role(&ctx.http, chapter_role_id).await?;
 
     member.remove_role(&ctx.http, new_member_role_id).await?;
@@ -124,19 +259,146 @@ pub async fn nmi_modal_response(ctx: &Context, interaction: &ModalInteraction) -
 
     member.add_role(&ctx.http, chapter_role_id).await?;
 
-    interaction.user.direct_message(&ctx.http, CreateMessage::new()
-        // TODO: Embed
-        .content(
-            format!("Congratulations! {} Welcome to the Old Gods! Your GM will review your character and promote them in-game.", emoji_party_popper()))
-    ).await?;
+    if let Some(faction_role_id) = chapter.faction_role_id(faction) {
+        member.add_role(&ctx.http, serenity::model::id::RoleId::new(faction_role_id)).await?;
+    }
+
+    if let Some(flavour_role_id) = config.flavour_role_id(chapter.flavour) {
+        member.add_role(&ctx.http, serenity::model::id::RoleId::new(flavour_role_id)).await?;
+    }
+
+    apply_nickname_policy(ctx, &member, character_name, realm_name, &chapter.name).await;
+
+    if let Err(e) = WaitlistEntry::remove_member(guild_id.to_string(), user.id.to_string()).await {
+        println!("Error removing member from waitlists: {}", e);
+    }
+
+    let dm_vars = chapter_vars(chapter, member.user.id, character_name, realm_name);
+    // Members with closed DMs still get their welcome and card.
+    if let Err(e) = user.direct_message(&ctx.http, CreateMessage::new()
+        .embed(create_onboarding_dm_embed(&templates, chapter, &dm_vars))
+    ).await {
+        println!("Error sending onboarding DM to {}: {}", user.id, e);
+    }
+
+    if let Err(e) = post_chapter_welcome(ctx, guild_id, chapter, member.user.id, character_name, realm_name).await {
+        println!("Error posting chapter welcome: {}", e);
+    }
     
-    let channel_id = secrets.nmi_channel_id;
+    let channel_id = config.nmi_channel_id;
     let channel = ctx.http.get_channel(ChannelId::new(channel_id)).await?;
 
-    let new_msg_embeds = create_new_member_embeds(interaction, member.user.id.to_string().parse::<u64>().expect("Couldn't parse UserId as u64."), character_name.to_string(), realm_name.to_string());
-    let new_msg_buttons = create_new_member_buttons();
+    let extra_answers = extra_answer_fields(guild_id, &answers);
+
+    let new_msg_embeds = create_new_member_embeds(guild_id, member.user.id.get(), character_name.to_string(), realm_name.to_string(), chapter, faction, extra_answers);
+    let new_msg_buttons = create_new_member_buttons(guild_id);
+
+    // Stored before the card goes out, so a card that cannot be posted does not lose the submission.
+    let stored = store_onboarding_details(guild_id, member.user.id, character_name, realm_name, chapter, faction).await;
+
+    push_member_completion_message(ctx, &member, channel, chapter, new_msg_embeds, new_msg_buttons).await?;
+
+    // Members who had no record get one along with their card.
+    if !stored {
+        store_onboarding_details(guild_id, member.user.id, character_name, realm_name, chapter, faction).await;
+    }
+
+    Ok(())
+}
+
+/// Saves the submitted details on the member's record. Returns false when there is no record to save them on.
+async fn store_onboarding_details(guild_id: GuildId, user_id: UserId, character_name: &str, realm_name: &str, chapter: &Chapter, faction: Option<Faction>) -> bool {
+    let record_result = MemberJoinMessage::get_message_by_discord_user_id(guild_id.to_string(), user_id.to_string()).await;
+    match record_result {
+        Ok(record) => {
+            let result = record.update_details(character_name.to_string(), realm_name.to_string(), chapter.name.clone(), chapter.flavour, faction).await;
+            if let Err(e) = result {
+                println!("Error storing onboarding details in database: {}", e);
+            }
+            true
+        }
+        Err(e) => {
+            println!("Error getting message from database: {}", e);
+            false
+        }
+    }
+}
+
+/// Number of the chapter the member's invite recruits for, when they joined through one listed in `invite_chapters`.
+async fn invited_chapter(guild_id: GuildId, user_id: UserId) -> Option<usize> {
+    let record = MemberJoinMessage::get_message_by_discord_user_id(guild_id.to_string(), user_id.to_string()).await.ok()?;
+    let chapter_name = UsedInvite::chapter_name(guild_id, &record.invite_code?)?;
+    let chapters = Chapters::for_guild(guild_id);
+    chapters.find_id_by_name(&chapter_name).filter(|id| chapters.get_active_by_id(*id).is_some())
+}
+
+/// The flavour the member picked before the form, when the guild asks for one.
+async fn picked_flavour(guild_id: GuildId, user_id: UserId) -> Option<Flavour> {
+    // A pick left over from when the guild had several flavours no longer applies.
+    if Chapters::for_guild(guild_id).flavours().len() < 2 {
+        return None;
+    }
+
+    let answers = MemberAnswer::get_answers(guild_id.to_string(), user_id.to_string()).await.ok()?;
+    MemberAnswer::find(&answers, FIELD_FLAVOUR).and_then(Flavour::from_key)
+}
+
+fn chapter_vars(chapter: &Chapter, user_id: UserId, character_name: &str, realm_name: &str) -> [(&'static str, String); 7] {
+    [
+        ("member", format!("<@{}>", user_id)),
+        ("character", character_name.to_string()),
+        ("realm", realm_name.to_string()),
+        ("chapter", chapter.name.clone()),
+        ("gm", chapter.gm_user_id.map(|gm_user_id| format!("<@{}>", gm_user_id)).unwrap_or_default()),
+        ("chapter_channel", chapter.announcement_channel_id.map(|channel_id| format!("<#{}>", channel_id)).unwrap_or_default()),
+        ("blurb", chapter.welcome_blurb.clone().unwrap_or_default()),
+    ]
+}
 
-    push_member_completion_message(ctx, &member, channel, new_msg_embeds, new_msg_buttons).await?;
+/// The member's DM, with links to their chapter's GM and channel when the chapter has them.
+fn create_onboarding_dm_embed(templates: &Templates, chapter: &Chapter, vars: &[(&str, String)]) -> CreateEmbed {
+    let mut embed = templates.message("onboarding_dm").embed(vars);
+    if let Some(gm_user_id) = chapter.gm_user_id {
+        embed = embed.field(templates.text("dm_gm", vars), format!("<@{}>", gm_user_id), true);
+    }
+    if let Some(channel_id) = chapter.announcement_channel_id {
+        embed = embed.field(templates.text("dm_chapter_channel", vars), format!("<#{}>", channel_id), true);
+    }
+    if let Some(blurb) = &chapter.welcome_blurb {
+        embed = embed.field(chapter.name.clone(), blurb.clone(), false);
+    }
+
+    embed
+}
+
+/// Tells the chapter about its new member. Chapters without an announcement channel are skipped.
+async fn post_chapter_welcome(ctx: &Context, guild_id: GuildId, chapter: &Chapter, user_id: UserId, character_name: &str, realm_name: &str) -> Result<(), serenity::Error> {
+    let Some(channel_id) = chapter.announcement_channel_id else {
+        return Ok(());
+    };
+
+    let vars = chapter_vars(chapter, user_id, character_name, realm_name);
+    let message = CreateMessage::new()
+        .content(format!("<@{}>", user_id))
+        .embed(Templates::for_guild(guild_id).message("chapter_welcome").embed(&vars));
+    ChannelId::new(channel_id).send_message(&ctx.http, message).await?;
 
     Ok(())
 }
+
+/// Returns the text entered in the modal's input on the given row, or an empty string.
+pub fn modal_input_value(interaction: &ModalInteraction, row: usize) -> String {
+    interaction
+        .data
+        .components
+        .get(row)
+        .and_then(|row| row.components.first())
+        .and_then(|component| {
+            if let serenity::all::ActionRowComponent::InputText(input) = component {
+                input.value.clone()
+            } else {
+                None
+            }
+        })
+        .unwrap_or_default()
+}
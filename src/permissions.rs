@@ -0,0 +1,162 @@
+use serde::{Deserialize, Serialize};
+use serenity::all::{CommandInteraction, ComponentInteraction, GuildId, Member, ModalInteraction, UserId};
+use serenity::builder::{CreateInteractionResponse, CreateInteractionResponseMessage};
+use serenity::client::Context;
+use crate::chapters::Chapter;
+use crate::config;
+use crate::templates::Templates;
+
+/// Things officers can do. Each one is granted to the roles listed under its name in
+/// the `permissions` section of config.json.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Complete,
+    Undo,
+    Reject,
+    ChangeChapter,
+    ChangeCharacter,
+    /// Re-sending the onboarding form and resetting a member's onboarding.
+    Reset,
+    Admin,
+    Export,
+    Lookup,
+}
+
+impl Action {
+    pub fn key(&self) -> &'static str {
+        match self {
+            Action::Complete => "complete",
+            Action::Undo => "undo",
+            Action::Reject => "reject",
+            Action::ChangeChapter => "change_chapter",
+            Action::ChangeCharacter => "change_character",
+            Action::Reset => "reset",
+            Action::Admin => "admin",
+            Action::Export => "export",
+            Action::Lookup => "lookup",
+        }
+    }
+}
+
+/// Users in `authorized_ids` and holders of an admin role may do everything.
+/// Everyone else needs one of the roles mapped to the action.
+pub fn is_authorized(guild_id: GuildId, user_id: UserId, member: Option<&Member>, action: Action) -> bool {
+    is_admin(guild_id, user_id, member) || has_any_role(member, &action_roles(guild_id, action))
+}
+
+/// Like `is_authorized`, for actions on a member's card. A chapter with its own officer role
+/// is handled only by that role and global admins; other chapters fall back to the action roles.
+pub fn is_authorized_for_chapter(guild_id: GuildId, user_id: UserId, member: Option<&Member>, action: Action, chapter: Option<&Chapter>) -> bool {
+    match chapter.and_then(|chapter| chapter.officer_role_id) {
+        Some(officer_role_id) => is_admin(guild_id, user_id, member) || has_any_role(member, &[officer_role_id]),
+        None => is_authorized(guild_id, user_id, member, action),
+    }
+}
+
+fn is_admin(guild_id: GuildId, user_id: UserId, member: Option<&Member>) -> bool {
+    config::Config::for_guild(guild_id).authorized_ids.contains(&user_id.get()) || has_any_role(member, &action_roles(guild_id, Action::Admin))
+}
+
+fn has_any_role(member: Option<&Member>, role_ids: &[u64]) -> bool {
+    member
+        .map(|member| member.roles.iter().any(|role| role_ids.contains(&role.get())))
+        .unwrap_or(false)
+}
+
+fn action_roles(guild_id: GuildId, action: Action) -> Vec<u64> {
+    config::Config::for_guild(guild_id).permissions.get(&action).cloned().unwrap_or_default()
+}
+
+/// Roles that may perform the action, as named in the denial message.
+fn allowed_roles(guild_id: GuildId, action: Action, chapter: Option<&Chapter>) -> Vec<u64> {
+    match chapter.and_then(|chapter| chapter.officer_role_id) {
+        Some(officer_role_id) => vec![officer_role_id],
+        None => action_roles(guild_id, action),
+    }
+}
+
+fn denial_message(locale: &str, action: Action, roles: &[u64]) -> String {
+    let templates = Templates::for_locale(locale);
+
+    let action_name = templates.text(&format!("action_{}", action.key()), &[]);
+    if roles.is_empty() {
+        return templates.text("permission_denied", &[("action", action_name)]);
+    }
+
+    let role_mentions = roles.iter()
+        .map(|role_id| format!("<@&{}>", role_id))
+        .collect::<Vec<String>>()
+        .join(", ");
+    templates.text("permission_denied_roles", &[("action", action_name), ("roles", role_mentions)])
+}
+
+fn log_denial(user_id: UserId, action: Action, target: &str) {
+    println!("Denied {} to {} on {}", action.key(), user_id, target);
+}
+
+/// Checks a slash command. On denial the user gets an ephemeral explanation and false is returned.
+pub async fn authorize_command(ctx: &Context, command: &CommandInteraction, action: Action) -> Result<bool, serenity::Error> {
+    authorize_command_for_chapter(ctx, command, action, None).await
+}
+
+/// Like `authorize_command`, for context menu commands acting on one member, so a chapter's
+/// officer role applies just as it does on the member's card.
+pub async fn authorize_command_for_chapter(ctx: &Context, command: &CommandInteraction, action: Action, chapter: Option<&Chapter>) -> Result<bool, serenity::Error> {
+    let guild_id = config::Config::guild_or_primary(command.guild_id);
+    if is_authorized_for_chapter(guild_id, command.user.id, command.member.as_deref(), action, chapter) {
+        return Ok(true);
+    }
+
+    log_denial(command.user.id, action, &format!("/{}", command.data.name));
+    command.create_response(
+        &ctx.http,
+        CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new()
+                .content(denial_message(&command.locale, action, &allowed_roles(guild_id, action, chapter)))
+                .ephemeral(true)
+        ),
+    ).await?;
+
+    Ok(false)
+}
+
+/// Checks a button press on a member's card. On denial the user gets an ephemeral explanation and false is returned.
+pub async fn authorize_component(ctx: &Context, component: &ComponentInteraction, action: Action, chapter: Option<&Chapter>) -> Result<bool, serenity::Error> {
+    let guild_id = config::Config::guild_or_primary(component.guild_id);
+    if is_authorized_for_chapter(guild_id, component.user.id, component.member.as_ref(), action, chapter) {
+        return Ok(true);
+    }
+
+    log_denial(component.user.id, action, &component.data.custom_id);
+    component.create_response(
+        &ctx.http,
+        CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new()
+                .content(denial_message(&component.locale, action, &allowed_roles(guild_id, action, chapter)))
+                .ephemeral(true)
+        ),
+    ).await?;
+
+    Ok(false)
+}
+
+/// Checks a modal submitted from a member's card. On denial the user gets an ephemeral explanation and false is returned.
+pub async fn authorize_modal(ctx: &Context, modal: &ModalInteraction, action: Action, chapter: Option<&Chapter>) -> Result<bool, serenity::Error> {
+    let guild_id = config::Config::guild_or_primary(modal.guild_id);
+    if is_authorized_for_chapter(guild_id, modal.user.id, modal.member.as_ref(), action, chapter) {
+        return Ok(true);
+    }
+
+    log_denial(modal.user.id, action, &modal.data.custom_id);
+    modal.create_response(
+        &ctx.http,
+        CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new()
+                .content(denial_message(&modal.locale, action, &allowed_roles(guild_id, action, chapter)))
+                .ephemeral(true)
+        ),
+    ).await?;
+
+    Ok(false)
+}
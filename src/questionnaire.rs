@@ -0,0 +1,464 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+use serde::{Deserialize, Serialize};
+use serenity::all::{GuildId, InputTextStyle};
+use serenity::builder::{CreateActionRow, CreateInputText, CreateModal};
+use crate::chapters::{Chapters, Flavour};
+use crate::i18n;
+use crate::templates::Templates;
+
+static QUESTIONNAIRE_CACHE: RwLock<Option<Questionnaire>> = RwLock::new(None);
+
+// Discord modals hold at most five inputs.
+const MAX_FIELDS_PER_PAGE: usize = 5;
+// Discord refuses to open a modal whose inputs go past these.
+const MAX_LABEL_LENGTH: usize = 45;
+const MAX_PLACEHOLDER_LENGTH: usize = 100;
+const MAX_INPUT_LENGTH: u16 = 4000;
+
+// Field ids the onboarding flow depends on. Every questionnaire must ask for these.
+pub const FIELD_CHAPTER_NUMBER: &str = "chapter_number";
+pub const FIELD_CHARACTER_NAME: &str = "character_name";
+pub const FIELD_REALM_NAME: &str = "realm_name";
+// Not form fields: stored with the answers when the member picks their game before the form,
+// and their faction after it when the chapter is split.
+pub const FIELD_FLAVOUR: &str = "flavour";
+pub const FIELD_FACTION: &str = "faction";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Questionnaire {
+    pub pages: Vec<QuestionnairePage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuestionnairePage {
+    pub title: String,
+    /// Page titles keyed by locale, e.g. { "es": "Registro de personaje" }.
+    #[serde(default)]
+    pub localized_titles: HashMap<String, String>,
+    pub fields: Vec<QuestionnaireField>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuestionnaireField {
+    pub id: String,
+    pub label: String,
+    /// Labels keyed by locale. The plain label is used for locales not listed here.
+    #[serde(default)]
+    pub localized_labels: HashMap<String, String>,
+    #[serde(default)]
+    pub style: FieldStyle,
+    #[serde(default = "default_required")]
+    pub required: bool,
+    #[serde(default)]
+    pub min_length: Option<u16>,
+    #[serde(default)]
+    pub max_length: Option<u16>,
+    #[serde(default)]
+    pub placeholder: Option<String>,
+    #[serde(default)]
+    pub localized_placeholders: HashMap<String, String>,
+    #[serde(default)]
+    pub validator: FieldValidator,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldStyle {
+    #[default]
+    Short,
+    Paragraph,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldValidator {
+    #[default]
+    None,
+    Chapter,
+    Number,
+    Letters,
+}
+
+fn default_required() -> bool {
+    true
+}
+
+impl Questionnaire {
+    /// The questionnaire in use. A questionnaire.json that cannot be used is reported by the self-check,
+    /// and the built-in form is used instead so onboarding keeps working.
+    /// There is one questionnaire for every guild the bot serves; unlike chapters and rules it is not per guild.
+    pub fn load() -> Self {
+        // Try to read from cache first.
+        if let Ok(cache) = QUESTIONNAIRE_CACHE.read() && let Some(questionnaire) = cache.as_ref() {
+            return questionnaire.clone();
+        }
+
+        // Cache miss - load from disk, falling back to the built-in form.
+        let questionnaire = match Self::read_from_disk() {
+            Ok(questionnaire) => questionnaire,
+            Err(e) => {
+                println!("{} Using the built-in questionnaire.", e);
+                Questionnaire::default()
+            }
+        };
+
+        // Update cache.
+        if let Ok(mut cache) = QUESTIONNAIRE_CACHE.write() {
+            *cache = Some(questionnaire.clone());
+        }
+
+        questionnaire
+    }
+
+    /// Reads and checks questionnaire.json. Without the file the built-in form is used.
+    pub fn read_from_disk() -> Result<Self, String> {
+        let questionnaire: Questionnaire = match std::fs::File::open("questionnaire.json") {
+            Ok(file) => serde_json::from_reader(file).map_err(|e| format!("questionnaire.json is not valid: {}.", e))?,
+            Err(_) => Questionnaire::default(),
+        };
+        questionnaire.check()?;
+
+        Ok(questionnaire)
+    }
+
+    fn check(&self) -> Result<(), String> {
+        if self.pages.is_empty() {
+            return Err("questionnaire.json must have at least one page.".to_string());
+        }
+        for page in &self.pages {
+            if page.fields.is_empty() || page.fields.len() > MAX_FIELDS_PER_PAGE {
+                return Err(format!("questionnaire page \"{}\" must have between 1 and {} fields.", page.title, MAX_FIELDS_PER_PAGE));
+            }
+        }
+        // Answers are stored by field id, so a repeated id would overwrite an earlier answer.
+        let mut seen = std::collections::HashSet::from([FIELD_FLAVOUR, FIELD_FACTION]);
+        for field in self.pages.iter().flat_map(|page| page.fields.iter()) {
+            if !seen.insert(field.id.as_str()) {
+                return Err(format!("questionnaire.json uses the field id \"{}\" more than once, or for a value the bot stores itself.", field.id));
+            }
+            field.check()?;
+        }
+        for id in [FIELD_CHAPTER_NUMBER, FIELD_CHARACTER_NAME, FIELD_REALM_NAME] {
+            if self.get_field(id).is_none() {
+                return Err(format!("questionnaire.json is missing the \"{}\" field.", id));
+            }
+        }
+        // Onboarding looks the chapter up by this number, so it must be checked to be one.
+        if let Some(field) = self.get_field(FIELD_CHAPTER_NUMBER) && !matches!(field.validator, FieldValidator::Chapter) {
+            return Err(format!("questionnaire.json field \"{}\" must use the \"chapter\" validator.", FIELD_CHAPTER_NUMBER));
+        }
+
+        Ok(())
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    pub fn get_field(&self, id: &str) -> Option<&QuestionnaireField> {
+        self.pages.iter().flat_map(|page| page.fields.iter()).find(|field| field.id == id)
+    }
+
+    /// Fields answered by members beyond the ones onboarding itself needs, in form order.
+    pub fn extra_fields(&self) -> impl Iterator<Item = &QuestionnaireField> {
+        self.pages.iter()
+            .flat_map(|page| page.fields.iter())
+            .filter(|field| ![FIELD_CHAPTER_NUMBER, FIELD_CHARACTER_NAME, FIELD_REALM_NAME].contains(&field.id.as_str()))
+    }
+}
+
+impl Default for Questionnaire {
+    fn default() -> Self {
+        let fields = vec![
+            QuestionnaireField {
+                id: FIELD_CHAPTER_NUMBER.to_string(),
+                label: "Chapter Number:".to_string(),
+                localized_labels: HashMap::new(),
+                style: FieldStyle::Short,
+                required: true,
+                min_length: Some(1),
+                max_length: Some(2),
+                placeholder: Some("0 for Aegwynn, etc...".to_string()),
+                localized_placeholders: HashMap::new(),
+                validator: FieldValidator::Chapter,
+            },
+            QuestionnaireField {
+                id: FIELD_CHARACTER_NAME.to_string(),
+                label: "Character Name:".to_string(),
+                localized_labels: HashMap::new(),
+                style: FieldStyle::Short,
+                required: true,
+                min_length: Some(2),
+                max_length: Some(14),
+                placeholder: Some("Bjork".to_string()),
+                localized_placeholders: HashMap::new(),
+                validator: FieldValidator::None,
+            },
+            QuestionnaireField {
+                id: FIELD_REALM_NAME.to_string(),
+                label: "Realm Name:".to_string(),
+                localized_labels: HashMap::new(),
+                style: FieldStyle::Short,
+                required: true,
+                min_length: Some(2),
+                max_length: Some(20),
+                placeholder: Some("Tichondrius".to_string()),
+                localized_placeholders: HashMap::new(),
+                validator: FieldValidator::None,
+            },
+        ];
+
+        Questionnaire {
+            pages: vec![QuestionnairePage { title: "NMI Character Registration".to_string(), localized_titles: HashMap::new(), fields }],
+        }
+    }
+}
+
+impl QuestionnairePage {
+    /// Builds the modal for this page. The page index is carried in the custom id.
+    /// Fields listed in `prefilled` start out with the given value.
+    pub fn to_modal(&self, page_index: usize, locale: &str, prefilled: &[(&str, String)]) -> CreateModal {
+        let rows = self.fields.iter()
+            .map(|field| {
+                let value = prefilled.iter().find(|(id, _)| *id == field.id).map(|(_, value)| value.as_str());
+                CreateActionRow::InputText(field.to_input(locale, value))
+            })
+            .collect();
+        let title = i18n::localized(&self.localized_titles, locale).unwrap_or(&self.title);
+
+        CreateModal::new(format!("nmi_modal:{}", page_index), title)
+            .components(rows)
+    }
+}
+
+impl QuestionnaireField {
+    fn to_input(&self, locale: &str, value: Option<&str>) -> CreateInputText {
+        let style = match self.style {
+            FieldStyle::Short => InputTextStyle::Short,
+            FieldStyle::Paragraph => InputTextStyle::Paragraph,
+        };
+
+        let mut input = CreateInputText::new(style, self.label(locale), self.id.clone())
+            .required(self.required);
+        if let Some(min_length) = self.min_length {
+            input = input.min_length(min_length);
+        }
+        if let Some(max_length) = self.max_length {
+            input = input.max_length(max_length);
+        }
+        if let Some(placeholder) = i18n::localized(&self.localized_placeholders, locale).or(self.placeholder.as_deref()) {
+            input = input.placeholder(placeholder);
+        }
+        if let Some(value) = value {
+            input = input.value(value);
+        }
+
+        input
+    }
+
+    fn label(&self, locale: &str) -> &str {
+        i18n::localized(&self.localized_labels, locale).unwrap_or(&self.label)
+    }
+
+    /// Label without the trailing colon, for use as an embed field name.
+    pub fn display_name(&self, locale: &str) -> &str {
+        self.label(locale).trim_end_matches(':')
+    }
+
+    /// Returns a message for the member when the value is not acceptable.
+    /// Chapter numbers must belong to `flavour` when the member picked one.
+    /// Checks the field against Discord's limits for modal inputs, in every language it is given in.
+    fn check(&self) -> Result<(), String> {
+        let too_long = |texts: Vec<&String>, max_length: usize| texts.into_iter().any(|text| text.chars().count() > max_length);
+
+        if too_long(std::iter::once(&self.label).chain(self.localized_labels.values()).collect(), MAX_LABEL_LENGTH) {
+            return Err(format!("questionnaire.json field \"{}\" has a label longer than {} characters.", self.id, MAX_LABEL_LENGTH));
+        }
+        if too_long(self.placeholder.iter().chain(self.localized_placeholders.values()).collect(), MAX_PLACEHOLDER_LENGTH) {
+            return Err(format!("questionnaire.json field \"{}\" has a placeholder longer than {} characters.", self.id, MAX_PLACEHOLDER_LENGTH));
+        }
+        if self.min_length.max(self.max_length).is_some_and(|length| length > MAX_INPUT_LENGTH) {
+            return Err(format!("questionnaire.json field \"{}\" allows more than {} characters.", self.id, MAX_INPUT_LENGTH));
+        }
+
+        Ok(())
+    }
+
+    pub fn validate(&self, guild_id: GuildId, flavour: Option<Flavour>, value: &str, locale: &str) -> Result<(), String> {
+        let templates = Templates::for_locale(locale);
+        let vars = [("field", self.display_name(locale).to_string())];
+
+        if value.is_empty() {
+            return if self.required {
+                Err(templates.text("field_required", &vars))
+            } else {
+                Ok(())
+            };
+        }
+
+        match self.validator {
+            FieldValidator::None => Ok(()),
+            FieldValidator::Chapter => {
+                let chapters = Chapters::for_guild(guild_id);
+                let chapter = value.parse::<usize>().ok().and_then(|number| chapters.get_active_by_id(number));
+                match (chapter, flavour) {
+                    (None, _) => Err(templates.text("invalid_chapter", &vars)),
+                    (Some(chapter), Some(flavour)) if chapter.flavour != flavour => Err(templates.text("invalid_chapter_flavour", &[
+                        ("chapter", chapter.name.clone()),
+                        ("flavour", templates.text(&flavour.label_key(), &[])),
+                    ])),
+                    (Some(_), _) => Ok(()),
+                }
+            }
+            FieldValidator::Number => match value.parse::<u64>() {
+                Ok(_) => Ok(()),
+                Err(_) => Err(templates.text("field_not_number", &vars)),
+            },
+            FieldValidator::Letters => {
+                if value.chars().all(char::is_alphabetic) {
+                    Ok(())
+                } else {
+                    Err(templates.text("field_not_letters", &vars))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(id: &str, validator: FieldValidator) -> QuestionnaireField {
+        QuestionnaireField {
+            id: id.to_string(),
+            label: id.to_string(),
+            localized_labels: HashMap::new(),
+            style: FieldStyle::Short,
+            required: true,
+            min_length: None,
+            max_length: None,
+            placeholder: None,
+            localized_placeholders: HashMap::new(),
+            validator,
+        }
+    }
+
+    fn questionnaire(pages: Vec<Vec<QuestionnaireField>>) -> Questionnaire {
+        Questionnaire {
+            pages: pages.into_iter()
+                .map(|fields| QuestionnairePage { title: "Page".to_string(), localized_titles: HashMap::new(), fields })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn built_in_questionnaire_passes_check() {
+        assert!(Questionnaire::default().check().is_ok());
+    }
+
+    #[test]
+    fn check_rejects_missing_required_field() {
+        let questionnaire = questionnaire(vec![vec![
+            field(FIELD_CHAPTER_NUMBER, FieldValidator::Chapter),
+            field(FIELD_CHARACTER_NAME, FieldValidator::None),
+        ]]);
+        assert!(questionnaire.check().unwrap_err().contains(FIELD_REALM_NAME));
+    }
+
+    #[test]
+    fn check_rejects_chapter_number_without_chapter_validator() {
+        let questionnaire = questionnaire(vec![vec![
+            field(FIELD_CHAPTER_NUMBER, FieldValidator::Number),
+            field(FIELD_CHARACTER_NAME, FieldValidator::None),
+            field(FIELD_REALM_NAME, FieldValidator::None),
+        ]]);
+        assert!(questionnaire.check().unwrap_err().contains("chapter"));
+    }
+
+    #[test]
+    fn check_rejects_empty_and_oversized_pages() {
+        assert!(questionnaire(vec![]).check().is_err());
+
+        let mut fields = vec![
+            field(FIELD_CHAPTER_NUMBER, FieldValidator::Chapter),
+            field(FIELD_CHARACTER_NAME, FieldValidator::None),
+            field(FIELD_REALM_NAME, FieldValidator::None),
+        ];
+        fields.extend((0..3).map(|index| field(&format!("extra_{}", index), FieldValidator::None)));
+        assert!(questionnaire(vec![fields]).check().is_err());
+    }
+
+    fn valid_fields() -> Vec<QuestionnaireField> {
+        vec![
+            field(FIELD_CHAPTER_NUMBER, FieldValidator::Chapter),
+            field(FIELD_CHARACTER_NAME, FieldValidator::None),
+            field(FIELD_REALM_NAME, FieldValidator::None),
+        ]
+    }
+
+    #[test]
+    fn check_rejects_duplicate_field_ids() {
+        let mut fields = valid_fields();
+        fields.push(field(FIELD_REALM_NAME, FieldValidator::None));
+        assert!(questionnaire(vec![fields]).check().unwrap_err().contains(FIELD_REALM_NAME));
+
+        let questionnaire = questionnaire(vec![valid_fields(), vec![field(FIELD_CHARACTER_NAME, FieldValidator::None)]]);
+        assert!(questionnaire.check().unwrap_err().contains(FIELD_CHARACTER_NAME));
+    }
+
+    #[test]
+    fn check_rejects_ids_the_bot_stores_itself() {
+        let mut fields = valid_fields();
+        fields.push(field(FIELD_FACTION, FieldValidator::None));
+        assert!(questionnaire(vec![fields]).check().unwrap_err().contains(FIELD_FACTION));
+    }
+
+    #[test]
+    fn check_rejects_long_labels() {
+        let mut fields = valid_fields();
+        fields[1].label = "a".repeat(MAX_LABEL_LENGTH + 1);
+        assert!(questionnaire(vec![fields]).check().unwrap_err().contains("label"));
+
+        let mut fields = valid_fields();
+        fields[1].localized_labels.insert("de".to_string(), "ä".repeat(MAX_LABEL_LENGTH + 1));
+        assert!(questionnaire(vec![fields]).check().unwrap_err().contains("label"));
+
+        // Limits count characters, not bytes.
+        let mut fields = valid_fields();
+        fields[1].label = "ä".repeat(MAX_LABEL_LENGTH);
+        assert!(questionnaire(vec![fields]).check().is_ok());
+    }
+
+    #[test]
+    fn check_rejects_long_placeholders() {
+        let mut fields = valid_fields();
+        fields[2].placeholder = Some("a".repeat(MAX_PLACEHOLDER_LENGTH + 1));
+        assert!(questionnaire(vec![fields]).check().unwrap_err().contains("placeholder"));
+
+        let mut fields = valid_fields();
+        fields[2].localized_placeholders.insert("es".to_string(), "a".repeat(MAX_PLACEHOLDER_LENGTH + 1));
+        assert!(questionnaire(vec![fields]).check().unwrap_err().contains("placeholder"));
+    }
+
+    #[test]
+    fn check_rejects_lengths_past_the_input_limit() {
+        let mut fields = valid_fields();
+        fields[1].max_length = Some(MAX_INPUT_LENGTH + 1);
+        assert!(questionnaire(vec![fields]).check().unwrap_err().contains("allows more than"));
+
+        let mut fields = valid_fields();
+        fields[1].max_length = Some(MAX_INPUT_LENGTH);
+        assert!(questionnaire(vec![fields]).check().is_ok());
+    }
+
+    #[test]
+    fn check_accepts_fields_spread_over_pages() {
+        let questionnaire = questionnaire(vec![
+            vec![field(FIELD_CHAPTER_NUMBER, FieldValidator::Chapter)],
+            vec![field(FIELD_CHARACTER_NAME, FieldValidator::None), field(FIELD_REALM_NAME, FieldValidator::Letters)],
+        ]);
+        assert!(questionnaire.check().is_ok());
+    }
+}
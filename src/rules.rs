@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use serenity::all::{ButtonStyle, ChannelId, CommandInteraction, CommandOptionType, ComponentInteraction, CreateCommand, CreateCommandOption, GuildId, ResolvedValue, RoleId, UserId};
+use serenity::builder::{CreateButton, CreateEmbed, CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage, EditInteractionResponse};
+use serenity::client::Context;
+use serenity::model::Timestamp;
+use crate::i18n;
+use crate::member_db::{MemberJoinMessage, MemberJoinMessageStage, RulesAcceptance};
+use crate::permissions::{authorize_command, Action};
+use crate::config;
+use crate::templates::Templates;
+
+static RULES_CACHE: RwLock<Option<HashMap<u64, Rules>>> = RwLock::new(None);
+
+// Members lose their role in batches, so a big guild stays clear of Discord's rate limits.
+const WITHHOLD_BATCH_SIZE: usize = 10;
+const WITHHOLD_BATCH_PAUSE: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Rules {
+    /// Bumped by `/rules publish` every time the text changes.
+    pub version: i64,
+    /// Oldest accepted version that still lets a member through the gate.
+    pub required_version: i64,
+    pub text: String,
+    /// Guild these rules belong to. Not stored in the file; each guild has its own file, as acceptances are per guild.
+    #[serde(skip)]
+    pub guild_id: u64,
+}
+
+impl Rules {
+    pub fn for_guild(guild_id: GuildId) -> Self {
+        // Try to read from cache first.
+        if let Ok(cache) = RULES_CACHE.read() && let Some(rules) = cache.as_ref().and_then(|guilds| guilds.get(&guild_id.get())) {
+            return rules.clone();
+        }
+
+        // Cache miss - load from disk.
+        let rules = Self::read_from_disk(guild_id);
+        rules.replace_cache();
+
+        rules
+    }
+
+    // No rules file means the gate is switched off for the guild.
+    fn read_from_disk(guild_id: GuildId) -> Self {
+        let path = config::Config::rules_path(guild_id);
+        let mut rules: Rules = match std::fs::File::open(&path) {
+            Ok(file) => serde_json::from_reader(file).unwrap_or_else(|e| panic!("{} not valid: {}", path, e)),
+            Err(_) => Rules::default(),
+        };
+        rules.guild_id = guild_id.get();
+
+        rules
+    }
+
+    pub fn save(&self) {
+        let path = config::Config::rules_path(GuildId::new(self.guild_id));
+        let file = std::fs::File::create(&path).unwrap_or_else(|_| panic!("Could not create {}", path));
+        serde_json::to_writer_pretty(file, &self).unwrap_or_else(|_| panic!("Could not write to {}", path));
+
+        self.replace_cache();
+    }
+
+    fn replace_cache(&self) {
+        if let Ok(mut cache) = RULES_CACHE.write() {
+            cache.get_or_insert_with(HashMap::new).insert(self.guild_id, self.clone());
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.text.is_empty()
+    }
+
+    fn to_embed(&self, locale: &str) -> CreateEmbed {
+        Templates::for_locale(locale).message("rules").embed(&self.template_vars())
+    }
+
+    fn template_vars(&self) -> [(&'static str, String); 2] {
+        [("version", self.version.to_string()), ("rules", self.text.clone())]
+    }
+}
+
+/// Returns true when the member still has to accept the current rules before onboarding.
+pub async fn needs_acceptance(guild_id: GuildId, discord_user_id: u64) -> bool {
+    let rules = Rules::for_guild(guild_id);
+    if !rules.is_enabled() {
+        return false;
+    }
+
+    !has_accepted(guild_id, discord_user_id, rules.required_version).await
+}
+
+async fn has_accepted(guild_id: GuildId, discord_user_id: u64, required_version: i64) -> bool {
+    match RulesAcceptance::get_latest(guild_id.to_string(), discord_user_id.to_string()).await {
+        Ok(acceptance) => acceptance.version >= required_version,
+        Err(_) => false,
+    }
+}
+
+/// Takes the member role from onboarded members who have not accepted `required_version`.
+/// They get it back when they accept. Returns how many members still in the guild must accept.
+async fn withhold_member_role(ctx: &Context, guild_id: GuildId, required_version: i64) -> Result<usize, serenity::Error> {
+    let member_role_id = RoleId::new(config::Config::for_guild(guild_id).member_role_id);
+    let records = MemberJoinMessage::get_all(guild_id.to_string()).await
+        .map_err(|_| serenity::Error::Other("Could not load onboarding records"))?;
+    let onboarded = MemberJoinMessage::latest_per_member(records).into_iter()
+        .filter(|record| matches!(record.stage, MemberJoinMessageStage::Completed));
+
+    let mut outdated = 0;
+    for record in onboarded {
+        if has_accepted(guild_id, record.discord_user_id, required_version).await {
+            continue;
+        }
+        // Members who left have nothing to withhold.
+        let Ok(member) = guild_id.member(&ctx.http, UserId::new(record.discord_user_id)).await else {
+            continue;
+        };
+
+        if outdated > 0 && outdated % WITHHOLD_BATCH_SIZE == 0 {
+            tokio::time::sleep(WITHHOLD_BATCH_PAUSE).await;
+        }
+        outdated += 1;
+        if member.roles.contains(&member_role_id) && let Err(e) = member.remove_role(&ctx.http, member_role_id).await {
+            println!("Error withholding member role from {}: {}", member.user.id, e);
+        }
+    }
+
+    Ok(outdated)
+}
+
+/// Gives an onboarded member back the role withheld until they accepted the current rules.
+async fn restore_member_role(ctx: &Context, guild_id: GuildId, interaction: &ComponentInteraction) -> Result<(), serenity::Error> {
+    let Ok(record) = MemberJoinMessage::get_message_by_discord_user_id(guild_id.to_string(), interaction.user.id.to_string()).await else {
+        return Ok(());
+    };
+    let Some(member) = interaction.member.as_ref() else {
+        return Ok(());
+    };
+
+    let member_role_id = RoleId::new(config::Config::for_guild(guild_id).member_role_id);
+    if matches!(record.stage, MemberJoinMessageStage::Completed) && !member.roles.contains(&member_role_id) {
+        member.add_role(&ctx.http, member_role_id).await?;
+    }
+
+    Ok(())
+}
+
+/// Shows the rules with an "I accept" button in place of the chapter form.
+/// `then_open_form` decides whether accepting continues straight into the questionnaire.
+pub async fn show_rules_gate(ctx: &Context, interaction: &ComponentInteraction, then_open_form: bool) -> Result<(), serenity::Error> {
+    let rules = Rules::for_guild(config::Config::guild_or_primary(interaction.guild_id));
+
+    let response = CreateInteractionResponseMessage::new()
+        .embed(rules.to_embed(&interaction.locale))
+        .button(create_accept_button(then_open_form, &interaction.locale))
+        .ephemeral(true);
+
+    interaction.create_response(&ctx.http, CreateInteractionResponse::Message(response)).await?;
+
+    Ok(())
+}
+
+fn create_accept_button(then_open_form: bool, locale: &str) -> CreateButton {
+    let custom_id = if then_open_form { "rules_accept:form" } else { "rules_accept" };
+
+    Templates::for_locale(locale).message("rules").button_with_label_key(custom_id, "rules_accept", ButtonStyle::Success, &[])
+}
+
+/// Records the acceptance. Returns true when the caller should continue into the chapter form.
+pub async fn handle_rules_accept(ctx: &Context, interaction: &ComponentInteraction) -> Result<bool, serenity::Error> {
+    let guild_id = config::Config::guild_or_primary(interaction.guild_id);
+    let rules = Rules::for_guild(guild_id);
+    let templates = Templates::for_locale(&interaction.locale);
+
+    let result = RulesAcceptance::record(guild_id.to_string(), interaction.user.id.to_string(), rules.version, Timestamp::now().to_string()).await;
+    if let Err(e) = result {
+        println!("Error recording rules acceptance in database: {}", e);
+        interaction.create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(templates.text("rules_accept_failed", &[]))
+                    .ephemeral(true)
+            ),
+        ).await?;
+        return Ok(false);
+    }
+
+    if let Err(e) = restore_member_role(ctx, guild_id, interaction).await {
+        println!("Error restoring member role of {}: {}", interaction.user.id, e);
+    }
+
+    if interaction.data.custom_id == "rules_accept:form" {
+        return Ok(true);
+    }
+
+    interaction.create_response(
+        &ctx.http,
+        CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new()
+                .content(templates.text("rules_accepted", &rules.template_vars()))
+                .ephemeral(true)
+        ),
+    ).await?;
+
+    Ok(false)
+}
+
+pub async fn register_rules_command() -> CreateCommand {
+    let require_reaccept = CreateCommandOption::new(CommandOptionType::Boolean, "require_reaccept", "Take the member role from existing members until they accept the new version.")
+        .required(false);
+
+    let publish = CreateCommandOption::new(CommandOptionType::SubCommand, "publish", "Publish the current text of this guild's rules file as a new version.")
+        .add_sub_option(require_reaccept);
+
+    CreateCommand::new("rules").description("Manage the guild rules.")
+        .add_option(publish)
+}
+
+pub async fn handle_rules_command(ctx: &Context, command: &CommandInteraction) -> Result<(), serenity::Error> {
+    let guild_id = config::Config::guild_or_primary(command.guild_id);
+    let config = config::Config::for_guild(guild_id);
+    let templates = Templates::for_locale(&command.locale);
+    if !authorize_command(ctx, command, Action::Admin).await? {
+        return Ok(());
+    }
+
+    let mut require_reaccept = false;
+    for option in command.data.options() {
+        if let ResolvedValue::SubCommand(sub_options) = option.value {
+            for sub_option in sub_options {
+                if let ("require_reaccept", ResolvedValue::Boolean(value)) = (sub_option.name, sub_option.value) {
+                    require_reaccept = value;
+                }
+            }
+        }
+    }
+
+    // Pick up edits made to the rules file since it was cached.
+    let mut rules = Rules::read_from_disk(guild_id);
+    if !rules.is_enabled() {
+        command.create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::default()
+                    .ephemeral(true)
+                    .content(templates.text("rules_empty", &[("file", config::Config::rules_path(guild_id))]))
+            ),
+        ).await?;
+        return Ok(());
+    }
+
+    rules.version += 1;
+    if require_reaccept {
+        rules.required_version = rules.version;
+    }
+    rules.save();
+
+    // Withholding the member role touches every onboarded member, which takes far longer than an interaction may wait.
+    if require_reaccept {
+        command.create_response(
+            &ctx.http,
+            CreateInteractionResponse::Defer(CreateInteractionResponseMessage::new().ephemeral(true)),
+        ).await?;
+    }
+
+    let mut reply = templates.text("rules_published", &rules.template_vars());
+    if require_reaccept {
+        // Announced first, so members can accept while their roles are still being withheld.
+        let guild_templates = Templates::for_guild(guild_id);
+        let guild_locale = i18n::guild_locale(guild_id);
+        let announcement = CreateMessage::new()
+            .content(guild_templates.text("rules_updated", &[]))
+            .embed(rules.to_embed(&guild_locale))
+            .button(create_accept_button(false, &guild_locale));
+        // The new version is live either way, so a failed announcement must not swallow the reply.
+        if let Err(e) = ChannelId::new(config.welcome_channel_id).send_message(&ctx.http, announcement).await {
+            println!("Error announcing updated rules: {}", e);
+        }
+
+        reply += " ";
+        reply += &match withhold_member_role(ctx, guild_id, rules.required_version).await {
+            Ok(outdated) => templates.text("rules_reaccept_count", &[("count", outdated.to_string())]),
+            Err(e) => {
+                println!("Error withholding member roles: {}", e);
+                templates.text("rules_reaccept_failed", &[("error", e.to_string())])
+            }
+        };
+        command.edit_response(&ctx.http, EditInteractionResponse::new().content(reply)).await?;
+        return Ok(());
+    }
+
+    command.create_response(
+        &ctx.http,
+        CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::default()
+                .ephemeral(true)
+                .content(reply)
+        ),
+    ).await?;
+
+    Ok(())
+}
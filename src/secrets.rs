@@ -3,18 +3,16 @@ use std::sync::RwLock;
 
 static SECRETS_CACHE: RwLock<Option<Secrets>> = RwLock::new(None);
 
+// The token is read from the first of these that is set.
+const TOKEN_VAR: &str = "NMI_TOKEN";
+// Path to a file holding only the token, e.g. a mounted Docker or Kubernetes secret.
+const TOKEN_FILE_VAR: &str = "NMI_TOKEN_FILE";
+pub const TOKEN_VARS: [&str; 2] = [TOKEN_VAR, TOKEN_FILE_VAR];
+
+/// Credentials only. Every other setting lives in `Config`.
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Secrets {
-    pub guild_id: u64,
     pub token: String,
-
-    pub authorized_ids: Vec<u64>,
-
-    pub nmi_channel_id: u64,
-    pub welcome_channel_id: u64,
-    pub new_member_role_id: u64,
-    pub guest_role_id: u64,
-    pub member_role_id: u64
 }
 
 impl Secrets {
@@ -25,26 +23,30 @@ impl Secrets {
                 return secrets.clone();
             }
         }
-        
-        // Cache miss - load from disk.
-        let file = std::fs::File::open("secrets.json").expect("secrets.json not found");
-        let secrets: Secrets = serde_json::from_reader(file).expect("secrets.json not valid");
-        
+
+        // Cache miss - read from the environment, falling back to secrets.json.
+        let secrets = Self::read_token();
+
         // Update cache.
         if let Ok(mut cache) = SECRETS_CACHE.write() {
             *cache = Some(secrets.clone());
         }
-        
+
         secrets
     }
-    
-    pub fn save_secrets(&self) {
-        let file = std::fs::File::create("secrets.json").expect("Secrets.json couldn't be opened.");
-        serde_json::to_writer(file, &self).expect("Failed to write secrets to file");
-        
-        // Update cache with new values.
-        if let Ok(mut cache) = SECRETS_CACHE.write() {
-            *cache = Some(self.clone());
+
+    fn read_token() -> Secrets {
+        if let Ok(token) = std::env::var(TOKEN_VAR) {
+            return Secrets { token };
         }
+
+        if let Ok(path) = std::env::var(TOKEN_FILE_VAR) {
+            let token = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("Could not read {} from {}: {}", TOKEN_FILE_VAR, path, e));
+            return Secrets { token: token.trim().to_string() };
+        }
+
+        let file = std::fs::File::open("secrets.json")
+            .unwrap_or_else(|_| panic!("No token found. Set {} or {}, or create secrets.json.", TOKEN_VAR, TOKEN_FILE_VAR));
+        serde_json::from_reader(file).expect("secrets.json not valid")
     }
 }
@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::fmt;
+use serenity::all::{ChannelId, GuildChannel, GuildId, Member, PartialGuild, Permissions, RoleId};
+use serenity::builder::CreateMessage;
+use serenity::client::Context;
+use crate::chapters::Chapters;
+use crate::permissions::Action;
+use crate::questionnaire::Questionnaire;
+use crate::templates::Templates;
+use crate::config;
+
+// Leaves room for the code fence inside Discord's 2000 character limit.
+const MAX_POSTED_LENGTH: usize = 1900;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The bot can run, but some feature or chapter will not work.
+    Warning,
+    /// Onboarding cannot work at all. The bot exits.
+    Fatal,
+}
+
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub severity: Severity,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub findings: Vec<Finding>,
+}
+
+impl Report {
+    fn warn(&mut self, message: String) {
+        self.report(Severity::Warning, message);
+    }
+
+    fn fatal(&mut self, message: String) {
+        self.report(Severity::Fatal, message);
+    }
+
+    fn report(&mut self, severity: Severity, message: String) {
+        self.findings.push(Finding { severity, message });
+    }
+
+    pub fn has_fatal(&self) -> bool {
+        self.findings.iter().any(|finding| finding.severity == Severity::Fatal)
+    }
+
+    fn count(&self, severity: Severity) -> usize {
+        self.findings.iter().filter(|finding| finding.severity == severity).count()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Configuration check: {} fatal, {} warning(s)", self.count(Severity::Fatal), self.count(Severity::Warning))?;
+        for finding in &self.findings {
+            let label = match finding.severity {
+                Severity::Fatal => "FATAL",
+                Severity::Warning => "WARN ",
+            };
+            writeln!(f, "  [{}] {}", label, finding.message)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks a guild's settings and chapters against the live guild: that every id exists,
+/// that the bot can manage the roles it hands out and post in the channels it uses,
+/// and that chapter names are unique.
+pub async fn run(ctx: &Context, guild_id: GuildId) -> Result<Report, serenity::Error> {
+    let config = config::Config::for_guild(guild_id);
+    let chapters = Chapters::for_guild(guild_id);
+    let mut report = Report::default();
+
+    let guild = guild_id.to_partial_guild(&ctx.http).await?;
+    let channels = guild_id.channels(&ctx.http).await?;
+    let bot_id = ctx.cache.current_user().id;
+    let bot_member = guild_id.member(&ctx.http, bot_id).await?;
+
+    let bot_permissions = guild_permissions(&guild, &bot_member);
+    if !bot_permissions.manage_roles() {
+        report.fatal("The bot is missing the Manage Roles permission.".to_string());
+    }
+    if config.nickname_template.is_some() && !bot_permissions.manage_nicknames() {
+        report.warn("nickname_template is set but the bot is missing the Manage Nicknames permission.".to_string());
+    }
+    if !config.invite_chapters.is_empty() && !bot_permissions.manage_guild() {
+        report.warn("invite_chapters is set but the bot is missing the Manage Server permission, so it cannot tell which invite a member used.".to_string());
+    }
+
+    let bot_position = bot_member.roles.iter()
+        .filter_map(|role_id| guild.roles.get(role_id))
+        .map(|role| role.position)
+        .max()
+        .unwrap_or(0);
+
+    let core_roles = [
+        ("new_member_role_id", config.new_member_role_id, Severity::Fatal),
+        ("member_role_id", config.member_role_id, Severity::Fatal),
+        ("guest_role_id", config.guest_role_id, Severity::Warning),
+    ];
+    for (name, role_id, severity) in core_roles {
+        check_assignable_role(&mut report, &guild, bot_position, name, role_id, severity);
+    }
+    for (flavour, role_id) in &config.flavour_role_ids {
+        let name = format!("flavour_role_ids.{}", flavour.key());
+        check_assignable_role(&mut report, &guild, bot_position, &name, *role_id, Severity::Warning);
+    }
+
+    let core_channels = [
+        ("nmi_channel_id", config.nmi_channel_id),
+        ("welcome_channel_id", config.welcome_channel_id),
+    ];
+    for (name, channel_id) in core_channels {
+        check_channel(&mut report, &guild, &channels, &bot_member, name, channel_id, Severity::Fatal);
+    }
+
+    for (action, role_ids) in &config.permissions {
+        for role_id in role_ids {
+            if !role_exists(&guild, *role_id) {
+                report.warn(format!("permissions.{} lists role {}, which does not exist.", action.key(), role_id));
+            }
+        }
+    }
+    if config.authorized_ids.is_empty() && config.permissions.get(&Action::Admin).is_none_or(|role_ids| role_ids.is_empty()) {
+        report.warn("Nobody can run admin commands: authorized_ids and permissions.admin are both empty.".to_string());
+    }
+
+    if let Err(e) = Questionnaire::read_from_disk() {
+        report.warn(format!("{} The built-in questionnaire is used until it is fixed.", e));
+    }
+    for e in Templates::check_catalog_files() {
+        report.warn(format!("{} The built-in messages are used until it is fixed.", e));
+    }
+
+    // A broken file would be overwritten by the next chapter command, so it stops the bot; a missing one is a new guild.
+    if let Err(e) = Chapters::read_from_disk(guild_id) {
+        if std::path::Path::new(&config::Config::chapters_path(guild_id)).exists() {
+            report.fatal(format!("{}.", e));
+        } else {
+            report.warn(format!("{}, so the guild has no chapters.", e));
+        }
+    }
+    for name in chapters.duplicate_names() {
+        report.fatal(format!("More than one chapter is named {}.", name));
+    }
+    for (code, chapter_name) in &config.invite_chapters {
+        if chapters.find_id_by_name(chapter_name).and_then(|id| chapters.get_active_by_id(id)).is_none() {
+            report.warn(format!("invite_chapters maps invite {} to {}, which is not an active chapter.", code, chapter_name));
+        }
+    }
+
+    for chapter in chapters.chapters.iter().filter(|chapter| !chapter.archived) {
+        let name = format!("chapter {} role_id", chapter.name);
+        check_assignable_role(&mut report, &guild, bot_position, &name, chapter.role_id, Severity::Warning);
+        for (faction, role_id) in &chapter.faction_role_ids {
+            let name = format!("chapter {} faction_role_ids.{}", chapter.name, faction.key());
+            check_assignable_role(&mut report, &guild, bot_position, &name, *role_id, Severity::Warning);
+        }
+
+        if let Some(officer_role_id) = chapter.officer_role_id && !role_exists(&guild, officer_role_id) {
+            report.warn(format!("chapter {} officer_role_id {} does not exist.", chapter.name, officer_role_id));
+        }
+        if let Some(channel_id) = chapter.announcement_channel_id {
+            let name = format!("chapter {} announcement_channel_id", chapter.name);
+            check_channel(&mut report, &guild, &channels, &bot_member, &name, channel_id, Severity::Warning);
+        }
+    }
+
+    Ok(report)
+}
+
+/// Server-wide permissions from @everyone and the member's roles, before channel overwrites.
+fn guild_permissions(guild: &PartialGuild, member: &Member) -> Permissions {
+    let everyone_role_id = RoleId::new(guild.id.get());
+    let permissions = member.roles.iter()
+        .chain(std::iter::once(&everyone_role_id))
+        .filter_map(|role_id| guild.roles.get(role_id))
+        .fold(Permissions::empty(), |permissions, role| permissions | role.permissions);
+
+    if permissions.administrator() { Permissions::all() } else { permissions }
+}
+
+fn role_exists(guild: &PartialGuild, role_id: u64) -> bool {
+    role_id != 0 && guild.roles.contains_key(&RoleId::new(role_id))
+}
+
+/// The role must exist and sit below the bot's highest role, or Discord refuses to assign it.
+fn check_assignable_role(report: &mut Report, guild: &PartialGuild, bot_position: u16, name: &str, role_id: u64, severity: Severity) {
+    if role_id == 0 {
+        report.report(severity, format!("{} is not set.", name));
+        return;
+    }
+
+    match guild.roles.get(&RoleId::new(role_id)) {
+        None => report.report(severity, format!("{} {} does not exist.", name, role_id)),
+        Some(role) if role.position >= bot_position => {
+            report.report(severity, format!("{} ({}) is not below the bot's highest role, so the bot cannot assign it.", name, role.name));
+        }
+        Some(_) => {}
+    }
+}
+
+fn check_channel(report: &mut Report, guild: &PartialGuild, channels: &HashMap<ChannelId, GuildChannel>, bot_member: &Member, name: &str, channel_id: u64, severity: Severity) {
+    let channel = (channel_id != 0).then(|| channels.get(&ChannelId::new(channel_id))).flatten();
+    let Some(channel) = channel else {
+        report.report(severity, format!("{} {} does not exist in this guild.", name, channel_id));
+        return;
+    };
+
+    let needed = Permissions::VIEW_CHANNEL | Permissions::SEND_MESSAGES | Permissions::EMBED_LINKS;
+    let missing = needed - guild.user_permissions_in(channel, bot_member);
+    if !missing.is_empty() {
+        report.report(severity, format!("The bot is missing {} in #{} ({}).", missing.get_permission_names().join(", "), channel.name, name));
+    }
+}
+
+/// Posts the report to the guild's admin channel, when one is configured.
+pub async fn post_report(ctx: &Context, guild_id: GuildId, report: &Report) -> Result<(), serenity::Error> {
+    let Some(admin_channel_id) = config::Config::for_guild(guild_id).admin_channel_id else {
+        return Ok(());
+    };
+
+    // Long reports are cut to fit in one message. The console always has the full text.
+    let mut text = String::new();
+    for line in report.to_string().lines() {
+        if text.len() + line.len() > MAX_POSTED_LENGTH {
+            text += "…\n";
+            break;
+        }
+        text += line;
+        text += "\n";
+    }
+
+    let message = CreateMessage::new().content(format!("```\n{}```", text));
+    ChannelId::new(admin_channel_id).send_message(&ctx.http, message).await?;
+
+    Ok(())
+}
@@ -0,0 +1,346 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+use serde::{Deserialize, Serialize};
+use serenity::all::{ButtonStyle, CommandInteraction, GuildId, CommandOptionType, CreateCommand, CreateCommandOption, ResolvedValue};
+use serenity::builder::{CreateButton, CreateEmbed, CreateEmbedAuthor, CreateEmbedFooter, CreateInteractionResponse, CreateInteractionResponseMessage};
+use serenity::client::Context;
+use serenity::model::colour;
+use crate::i18n;
+use crate::permissions::{authorize_command, Action};
+
+// Catalogs keyed by locale.
+static TEMPLATES_CACHE: RwLock<Option<HashMap<String, Templates>>> = RwLock::new(None);
+
+// Built-in English copy. The files in locales/, messages.json and messages.<locale>.json are layered on top of it.
+const DEFAULT_MESSAGES: &str = include_str!("default_messages.json");
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Templates {
+    #[serde(default)]
+    pub messages: HashMap<String, MessageTemplate>,
+    #[serde(default)]
+    pub strings: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MessageTemplate {
+    #[serde(default)]
+    pub author: Option<String>,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Hex colour such as "#A724FF".
+    #[serde(default)]
+    pub color: Option<String>,
+    #[serde(default)]
+    pub footer: Option<String>,
+    #[serde(default)]
+    pub fields: Vec<FieldTemplate>,
+    /// Button labels keyed by the button's custom id.
+    #[serde(default)]
+    pub buttons: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldTemplate {
+    pub name: String,
+    pub value: String,
+    #[serde(default)]
+    pub inline: bool,
+}
+
+/// Replaces every `{name}` placeholder with its value.
+pub fn render(text: &str, vars: &[(&str, String)]) -> String {
+    let mut rendered = text.to_string();
+    for (name, value) in vars {
+        rendered = rendered.replace(&format!("{{{}}}", name), value);
+    }
+    rendered
+}
+
+impl Templates {
+    /// Catalog in the guild's default locale, for messages everyone sees.
+    pub fn for_guild(guild_id: GuildId) -> Self {
+        Self::for_locale(&i18n::guild_locale(guild_id))
+    }
+
+    /// Catalog for a Discord locale such as "es-ES". Keys the locale does not translate fall back to English.
+    pub fn for_locale(locale: &str) -> Self {
+        // Try to read from cache first.
+        if let Ok(cache) = TEMPLATES_CACHE.read() && let Some(templates) = cache.as_ref().and_then(|catalogs| catalogs.get(locale)) {
+            return templates.clone();
+        }
+
+        // Cache miss - load from disk. A broken catalog is reported by the self-check; until it is fixed the built-in copy is used.
+        let templates = Self::read_locale_from_disk(locale).unwrap_or_else(|e| {
+            println!("{} Using the built-in messages.", e);
+            Self::built_in()
+        });
+
+        // Update cache.
+        if let Ok(mut cache) = TEMPLATES_CACHE.write() {
+            cache.get_or_insert_with(HashMap::new).insert(locale.to_string(), templates.clone());
+        }
+
+        templates
+    }
+
+    /// Reads a catalog without touching the cache. Layers, from lowest to highest: the built-in English copy, the bundled locales/<language>.json
+    /// and locales/<locale>.json, the operator's messages.json, then the operator's
+    /// messages.<language>.json and messages.<locale>.json. Copy the operator customised in
+    /// messages.json therefore wins over the bundled translations; per-language versions of that
+    /// copy go in messages.<locale>.json, e.g. messages.es.json.
+    pub fn read_locale_from_disk(locale: &str) -> Result<Self, String> {
+        let mut templates = Self::built_in();
+
+        let candidates = i18n::candidates(locale);
+        for candidate in candidates.iter().rev() {
+            if let Some(translations) = Self::read_overrides(&format!("locales/{}.json", candidate))? {
+                templates.overlay(translations);
+            }
+        }
+        if let Some(overrides) = Self::read_overrides("messages.json")? {
+            templates.overlay(overrides);
+        }
+        for candidate in candidates.iter().rev() {
+            if let Some(overrides) = Self::read_overrides(&format!("messages.{}.json", candidate))? {
+                templates.overlay(overrides);
+            }
+        }
+
+        Ok(templates)
+    }
+
+    fn built_in() -> Self {
+        serde_json::from_str(DEFAULT_MESSAGES).expect("Built-in messages are not valid")
+    }
+
+    /// A catalog file, or None when it does not exist.
+    fn read_overrides(path: &str) -> Result<Option<Self>, String> {
+        match std::fs::File::open(path) {
+            Ok(file) => serde_json::from_reader(file).map(Some).map_err(|e| format!("{} is not valid: {}.", path, e)),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Problems with the operator's messages files and the bundled translations, one per broken file.
+    pub fn check_catalog_files() -> Vec<String> {
+        let list = |dir: &str, prefix: &str| -> Vec<String> {
+            let Ok(entries) = std::fs::read_dir(dir) else {
+                return Vec::new();
+            };
+            entries.filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                .filter(|name| name.starts_with(prefix) && name.ends_with(".json"))
+                .map(|name| if dir == "." { name } else { format!("{}/{}", dir, name) })
+                .collect()
+        };
+
+        let mut paths = list(".", "messages.");
+        paths.extend(list("locales", ""));
+        paths.sort();
+        paths.iter().filter_map(|path| Self::read_overrides(path).err()).collect()
+    }
+
+    /// Applies another catalog on top of this one. Only the parts it sets are replaced,
+    /// so a translation can leave colours and untranslated strings alone.
+    fn overlay(&mut self, other: Templates) {
+        for (key, template) in other.messages {
+            self.messages.entry(key).or_default().overlay(template);
+        }
+        self.strings.extend(other.strings);
+    }
+
+    pub fn message(&self, key: &str) -> MessageTemplate {
+        self.messages.get(key).cloned().unwrap_or_default()
+    }
+
+    /// Renders a short string. Unknown keys render as the key itself so gaps are easy to spot.
+    pub fn text(&self, key: &str, vars: &[(&str, String)]) -> String {
+        match self.strings.get(key) {
+            Some(text) => render(text, vars),
+            None => key.to_string(),
+        }
+    }
+}
+
+impl MessageTemplate {
+    fn overlay(&mut self, other: MessageTemplate) {
+        if other.author.is_some() {
+            self.author = other.author;
+        }
+        if other.title.is_some() {
+            self.title = other.title;
+        }
+        if other.description.is_some() {
+            self.description = other.description;
+        }
+        if other.color.is_some() {
+            self.color = other.color;
+        }
+        if other.footer.is_some() {
+            self.footer = other.footer;
+        }
+        if !other.fields.is_empty() {
+            self.fields = other.fields;
+        }
+        self.buttons.extend(other.buttons);
+    }
+
+    pub fn embed(&self, vars: &[(&str, String)]) -> CreateEmbed {
+        let mut embed = CreateEmbed::new();
+        if let Some(author) = &self.author {
+            embed = embed.author(CreateEmbedAuthor::new(render(author, vars)));
+        }
+        if let Some(title) = &self.title {
+            embed = embed.title(render(title, vars));
+        }
+        if let Some(description) = &self.description {
+            embed = embed.description(render(description, vars));
+        }
+        if let Some(color) = self.colour() {
+            embed = embed.color(color);
+        }
+        if let Some(footer) = &self.footer {
+            embed = embed.footer(CreateEmbedFooter::new(render(footer, vars)));
+        }
+        for field in &self.fields {
+            embed = embed.field(render(&field.name, vars), render(&field.value, vars), field.inline);
+        }
+
+        embed
+    }
+
+    /// The description alone, for plain-text replies.
+    pub fn text(&self, vars: &[(&str, String)]) -> String {
+        render(self.description.as_deref().unwrap_or_default(), vars)
+    }
+
+    pub fn button(&self, custom_id: &str, style: ButtonStyle, vars: &[(&str, String)]) -> CreateButton {
+        self.button_with_label_key(custom_id, custom_id, style, vars)
+    }
+
+    /// Like `button`, for buttons whose custom id carries extra state (e.g. "nmi_continue:1").
+    pub fn button_with_label_key(&self, custom_id: &str, label_key: &str, style: ButtonStyle, vars: &[(&str, String)]) -> CreateButton {
+        let label = self.buttons.get(label_key).map(|label| render(label, vars)).unwrap_or_else(|| label_key.to_string());
+
+        CreateButton::new(custom_id)
+            .label(label)
+            .style(style)
+    }
+
+    fn colour(&self) -> Option<colour::Color> {
+        let hex = self.color.as_ref()?.trim_start_matches('#');
+        u32::from_str_radix(hex, 16).ok().map(colour::Color::new)
+    }
+}
+
+pub async fn register_template_command() -> CreateCommand {
+    let mut name = CreateCommandOption::new(CommandOptionType::String, "name", "Template to preview.")
+        .required(true);
+    let defaults = Templates::built_in();
+    let mut keys: Vec<&String> = defaults.messages.keys().collect();
+    keys.sort();
+    for key in keys {
+        name = name.add_string_choice(key, key);
+    }
+
+    let locale = CreateCommandOption::new(CommandOptionType::String, "locale", "Locale to preview, such as es-ES. Defaults to your own.")
+        .required(false);
+
+    let preview = CreateCommandOption::new(CommandOptionType::SubCommand, "preview", "Preview a message template from messages.json with sample values.")
+        .add_sub_option(name)
+        .add_sub_option(locale);
+
+    CreateCommand::new("template").description("Work with message templates.")
+        .add_option(preview)
+}
+
+pub async fn handle_template_command(ctx: &Context, command: &CommandInteraction) -> Result<(), serenity::Error> {
+    let templates = Templates::for_locale(&command.locale);
+
+    if !authorize_command(ctx, command, Action::Admin).await? {
+        return Ok(());
+    }
+
+    let mut key = String::new();
+    let mut locale = command.locale.clone();
+    for option in command.data.options() {
+        if let ResolvedValue::SubCommand(sub_options) = option.value {
+            for sub_option in sub_options {
+                match (sub_option.name, sub_option.value) {
+                    ("name", ResolvedValue::String(value)) => key = value.to_string(),
+                    ("locale", ResolvedValue::String(value)) => locale = value.to_string(),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    // Preview what is on disk right now, so copy can be checked before it goes live.
+    let preview_templates = match Templates::read_locale_from_disk(&locale) {
+        Ok(preview_templates) => preview_templates,
+        Err(e) => {
+            command.create_response(
+                &ctx.http,
+                CreateInteractionResponse::Message(
+                    CreateInteractionResponseMessage::default()
+                        .ephemeral(true)
+                        .content(e)
+                ),
+            ).await?;
+            return Ok(());
+        }
+    };
+
+    let Some(template) = preview_templates.messages.get(&key) else {
+        command.create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::default()
+                    .ephemeral(true)
+                    .content(templates.text("template_unknown", &[("template", key.clone())]))
+            ),
+        ).await?;
+        return Ok(());
+    };
+
+    let vars = sample_vars(command);
+    let mut labels: Vec<(&String, &String)> = template.buttons.iter().collect();
+    labels.sort();
+
+    let mut response = CreateInteractionResponseMessage::new()
+        .embed(template.embed(&vars))
+        .ephemeral(true);
+    for (custom_id, label) in labels {
+        // Disabled so clicking the preview never triggers the real handler.
+        let button = CreateButton::new(format!("template_preview:{}", custom_id))
+            .label(render(label, &vars))
+            .style(ButtonStyle::Secondary)
+            .disabled(true);
+        response = response.button(button);
+    }
+
+    command.create_response(&ctx.http, CreateInteractionResponse::Message(response)).await?;
+
+    Ok(())
+}
+
+fn sample_vars(command: &CommandInteraction) -> Vec<(&'static str, String)> {
+    vec![
+        ("member", format!("<@{}>", command.user.id)),
+        ("officer", format!("<@{}>", command.user.id)),
+        ("character", "Bjork".to_string()),
+        ("realm", "Tichondrius".to_string()),
+        ("chapter", "Tichondrius".to_string()),
+        ("gm", format!("<@{}>", command.user.id)),
+        ("chapter_channel", format!("<#{}>", command.channel_id)),
+        ("blurb", "Raids are Tuesday and Thursday at 8pm server time.".to_string()),
+        ("flavour", "Retail".to_string()),
+        ("chapter_list", crate::chapters::Chapters::for_guild(crate::config::Config::guild_or_primary(command.guild_id)).to_formatted_list()),
+        ("version", "1".to_string()),
+        ("rules", "1. Be respectful to every member.".to_string()),
+        ("page", "1".to_string()),
+        ("pages", "2".to_string()),
+    ]
+}
@@ -0,0 +1,66 @@
+use serenity::all::{CreateMessage, GuildId, UserId};
+use serenity::client::Context;
+use serenity::model::Timestamp;
+use crate::chapters::{Chapter, ChapterStatus, Chapters};
+use crate::member_db::{MemberJoinMessage, WaitlistEntry};
+use crate::templates::Templates;
+
+/// How many more members the chapter takes right now. Chapters without a cap have no limit.
+pub async fn free_slots(guild_id: GuildId, chapter: &Chapter) -> usize {
+    if chapter.status != ChapterStatus::Open {
+        return 0;
+    }
+    let Some(member_cap) = chapter.member_cap else {
+        return usize::MAX;
+    };
+
+    match MemberJoinMessage::count_in_chapter(guild_id.to_string(), chapter.name.clone()).await {
+        Ok(count) => (member_cap as usize).saturating_sub(count as usize),
+        Err(e) => {
+            // Better to let a member through than to queue everyone while the database is unreachable.
+            println!("Error counting chapter members: {}", e);
+            usize::MAX
+        }
+    }
+}
+
+/// Queues the member for the chapter. Returns their place in the queue.
+pub async fn join(guild_id: GuildId, user_id: UserId, chapter: &Chapter, locale: &str) -> Result<usize, serenity::Error> {
+    println!("{} joined the waitlist of {}", user_id, chapter.name);
+    WaitlistEntry::add(guild_id.to_string(), user_id.to_string(), chapter.name.clone(), locale.to_string(), Timestamp::now().to_string()).await
+        .map_err(|_| serenity::Error::Other("Could not store waitlist entry"))
+}
+
+/// Tells the longest waiting members of the chapter that a spot is free, one member per free spot,
+/// and takes them off the waitlist. They finish by filling in the form again.
+pub async fn notify_open_slots(ctx: &Context, guild_id: GuildId, chapter_name: &str) {
+    let chapters = Chapters::for_guild(guild_id);
+    let Some(chapter) = chapters.find_id_by_name(chapter_name).and_then(|id| chapters.get_active_by_id(id)) else {
+        return;
+    };
+
+    let slots = free_slots(guild_id, chapter).await;
+    if slots == 0 {
+        return;
+    }
+
+    let entries = match WaitlistEntry::get_for_chapter(guild_id.to_string(), chapter.name.clone()).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            println!("Error getting waitlist from database: {}", e);
+            return;
+        }
+    };
+
+    for entry in entries.into_iter().take(slots) {
+        let text = Templates::for_locale(&entry.locale).text("chapter_slot_open", &[("chapter", chapter.name.clone())]);
+        let result = UserId::new(entry.discord_user_id).direct_message(&ctx.http, CreateMessage::new().content(text)).await;
+        // Members with closed DMs are dropped all the same, so they do not hold up everyone behind them.
+        if let Err(e) = result {
+            println!("Error notifying {} of a spot in {}: {}", entry.discord_user_id, chapter.name, e);
+        }
+        if let Err(e) = entry.delete().await {
+            println!("Error removing waitlist entry from database: {}", e);
+        }
+    }
+}